@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use chrono::DateTime;
+use entity::{audit_event, prelude::*, sea_orm_active_enums::AuditOutcomeEnum};
+use id::{audit, db, param, wrap_error};
+use sea_orm::{prelude::*, QueryOrder, QuerySelect};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 500;
+
+/// Lists audit events, newest first.
+///
+/// Filters are `actor_id`, `subject` (like `passport:12`), `action`, `outcome`, and `since`
+/// and `until` as RFC 3339 times. Pass the `next_cursor` from one page as `cursor` to get the
+/// next.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+    let audit = audit::Event::new(&req, "audit.query").detail(json!(params));
+
+    let admin = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let mut query = AuditEvent::find();
+    if let Some(actor_id) = param::<i32>(&params, "actor_id")? {
+        query = query.filter(audit_event::Column::ActorId.eq(actor_id));
+    }
+    if let Some(subject) = params.get("subject") {
+        query = query.filter(audit_event::Column::Subject.eq(subject));
+    }
+    if let Some(action) = params.get("action") {
+        query = query.filter(audit_event::Column::Action.eq(action));
+    }
+    if let Some(outcome) = params.get("outcome") {
+        let outcome: AuditOutcomeEnum =
+            serde_json::from_value(serde_json::Value::String(outcome.to_string()))
+                .map_err(|e| format!("Unknown outcome! {e}"))?;
+        query = query.filter(audit_event::Column::Outcome.eq(outcome));
+    }
+    if let Some(since) = params.get("since") {
+        let since = DateTime::parse_from_rfc3339(since)?;
+        query = query.filter(audit_event::Column::CreatedAt.gte(since));
+    }
+    if let Some(until) = params.get("until") {
+        let until = DateTime::parse_from_rfc3339(until)?;
+        query = query.filter(audit_event::Column::CreatedAt.lt(until));
+    }
+    if let Some(cursor) = param::<i32>(&params, "cursor")? {
+        query = query.filter(audit_event::Column::Id.lt(cursor));
+    }
+
+    let limit = param::<u64>(&params, "limit")?
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
+
+    let db = db().await?;
+    audit.actor(admin).success(&db).await;
+
+    let mut events = query
+        .order_by_desc(audit_event::Column::Id)
+        .limit(limit + 1)
+        .all(&db)
+        .await?;
+
+    let next_cursor = if events.len() as u64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|e| e.id.to_string())
+    } else {
+        None
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "events": events,
+                "next_cursor": next_cursor,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
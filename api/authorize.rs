@@ -1,8 +1,19 @@
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use chrono::{Months, Utc};
-use entity::{auth_grant, auth_session, passport, sea_orm_active_enums::RoleEnum, user};
-use id::{db, kv, tfa, wrap_error, OAuthEndpoint, RequestCompat, ResponseCompat};
+use entity::{
+    auth_grant, auth_session, passport,
+    sea_orm_active_enums::AuditOutcomeEnum::{self, *},
+    user,
+};
+use id::{
+    audit, db, kv, lifecycle, roles, session,
+    step_up::{self, Authentication},
+    tfa, webauthn, wrap_error, OAuthEndpoint, RequestCompat, ResponseCompat,
+};
 
 use oxide_auth::{
     endpoint::{OwnerConsent, Solicitation, WebResponse},
@@ -13,14 +24,16 @@ use oxide_auth_async::endpoint::authorization::AuthorizationFlow;
 use entity::prelude::*;
 use fred::prelude::*;
 use lambda_http::http::{
-    header::{COOKIE, LOCATION, SET_COOKIE},
+    header::{LOCATION, SET_COOKIE},
     Method,
 };
 use oxide_auth_async::endpoint::OwnerSolicitor;
 
 use rand::distributions::{Alphanumeric, DistString};
-use sea_orm::{prelude::*, ActiveValue, Condition};
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use webauthn_rs::prelude::PublicKeyCredential;
 
+use serde_json::json;
 use url::Url;
 use vercel_runtime::{run, Body, Error, Request, Response};
 
@@ -29,7 +42,22 @@ async fn main() -> Result<(), Error> {
     run(wrap_error!(handler)).await
 }
 
-struct AuthorizeSolicitor;
+/// Works out whether the request may be authorized, noting how the user authenticated so the
+/// session and grant made from it can carry that along
+struct AuthorizeSolicitor {
+    authentication: Arc<Mutex<Option<Authentication>>>,
+}
+
+/// Records a refused login and gives the error to show for it
+async fn refuse(
+    db: &DatabaseConnection,
+    audit: &audit::Event,
+    outcome: AuditOutcomeEnum,
+    message: &str,
+) -> OwnerConsent<ResponseCompat> {
+    audit.record(db, outcome).await;
+    OwnerConsent::Error(message.to_string().into())
+}
 
 #[async_trait::async_trait]
 impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
@@ -38,23 +66,6 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
         req: &mut RequestCompat,
         solicitation: Solicitation<'_>,
     ) -> OwnerConsent<ResponseCompat> {
-        // If there is a session token, try to use that.
-        let session = 's: {
-            let cookies = req.headers().get_all(COOKIE);
-            for cookie in cookies {
-                for itm in cookie.to_str().unwrap_or_default().split("; ") {
-                    if itm.starts_with("session") {
-                        let mut s = itm.split("=");
-                        if let Some(v) = s.nth(1) {
-                            break 's Some(v);
-                        }
-                    }
-                }
-            }
-
-            None
-        };
-
         let url = url::Url::from_str(&req.uri().to_string()).expect("URL to be valid");
 
         let user_wants_allow = url
@@ -65,24 +76,83 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
             .expect("failed to parse allow");
 
         let db = db().await.expect("db to be accessible");
+        let pg = solicitation.pre_grant();
+        let audit = audit::Event::new(req, "login")
+            .client(pg.client_id.to_string())
+            .detail(json!({ "scope": pg.scope.to_string() }));
+
+        // If there is a session token, try to use that, unless this needs a fresher login
+        // than the session came from. Then only a new passport tap will do.
+        let session = session(&db, req).await.expect("session lookup to succeed");
+        if let Some(session) = session {
+            let audit = audit.clone().actor(session.owner_id);
+            if !step_up::required(&pg.client_id, &pg.scope) || step_up::is_fresh(&session.auth_time)
+            {
+                // The session only vouches for who logged in, so everything a tap would
+                // check about the account and passport still has to hold
+                let user: user::Model = User::find_by_id(session.owner_id)
+                    .one(&db)
+                    .await
+                    .expect("db op to succeed")
+                    .expect("Session to have an owner");
+                if user.disabled_at.is_some() {
+                    return refuse(&db, &audit, Denied, "Account disabled!").await;
+                }
+                if !roles::may_grant(&user.role, &pg.scope) {
+                    return refuse(
+                        &db,
+                        &audit,
+                        Denied,
+                        "Your role may not access these scopes!",
+                    )
+                    .await;
+                }
+                if let Some(passport_id) = session.passport_id {
+                    let passport: Option<passport::Model> = Passport::find_by_id(passport_id)
+                        .one(&db)
+                        .await
+                        .expect("db op to succeed");
+                    if let Some(passport) = passport {
+                        let passport = lifecycle::expire_if_due(&db, passport)
+                            .await
+                            .expect("expiry check to succeed");
+                        if let Some(refusal) = lifecycle::refusal(&passport.status) {
+                            return refuse(&db, &audit, Denied, refusal).await;
+                        }
+                    }
+                }
 
-        if let Some(token) = session {
-            // Validate the token
-            let session = AuthSession::find()
-                .filter(
-                    Condition::all()
-                        .add(auth_session::Column::Token.eq(token))
-                        .add(auth_session::Column::Until.gte(Utc::now())),
-                )
-                .one(&db)
-                .await
-                .unwrap();
-            if let Some(session) = session {
-                return if user_wants_allow {
-                    OwnerConsent::Authorized(session.owner_id.to_string())
-                } else {
-                    OwnerConsent::Denied
-                };
+                // Step-up needs the session to have come with a second factor, unless the
+                // user has none to give and isn't after admin scopes
+                let amr: Vec<String> = serde_json::from_value(session.amr).unwrap_or_default();
+                let stepped_up = !step_up::required(&pg.client_id, &pg.scope)
+                    || amr
+                        .iter()
+                        .any(|m| m == step_up::TOTP || m == step_up::PASSKEY)
+                    || (!roles::needs_totp(&pg.scope)
+                        && !tfa::enrolled(&db, &user)
+                            .await
+                            .expect("second factor lookup to succeed"));
+
+                if stepped_up {
+                    if !user_wants_allow {
+                        audit.record(&db, Denied).await;
+                        return OwnerConsent::Denied;
+                    }
+
+                    audit.success(&db).await;
+                    *self.authentication.lock().expect("lock not to be poisoned") =
+                        Some(Authentication {
+                            passport_id: session.passport_id,
+                            auth_time: session.auth_time,
+                            amr,
+                        });
+                    return OwnerConsent::Authorized(session.owner_id.to_string());
+                }
+            }
+
+            if !url.query_pairs().any(|(k, _)| k == "id") {
+                return refuse(&db, &audit, Denied, "Tap your passport again to continue!").await;
             }
         }
 
@@ -93,6 +163,7 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
             .expect("Passport ID to be given")
             .parse()
             .expect("ID to be valid integer");
+        let audit = audit.subject("passport", passport_id);
 
         let passport: Option<passport::Model> = Passport::find_by_id(passport_id)
             .one(&db)
@@ -101,11 +172,16 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
 
         let passport = match passport {
             Some(p) => p,
-            None => return OwnerConsent::Error("passport doesn't exist!".to_string().into()),
+            None => return refuse(&db, &audit, Failure, "passport doesn't exist!").await,
         };
+        let passport = lifecycle::expire_if_due(&db, passport)
+            .await
+            .expect("expiry check to succeed");
+
+        let audit = audit.actor(passport.owner_id);
 
-        if !passport.activated {
-            return OwnerConsent::Error("passport isn't activated!".to_string().into());
+        if let Some(refusal) = lifecycle::refusal(&passport.status) {
+            return refuse(&db, &audit, Denied, refusal).await;
         }
 
         // If it exists, now try to find in the Redis KV
@@ -116,7 +192,7 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
             .expect("redis op to succeed")
             == 0
         {
-            return OwnerConsent::Error("Passport has not been scanned!".to_string().into());
+            return refuse(&db, &audit, Failure, "Passport has not been scanned!").await;
         }
 
         let ready: bool = kv
@@ -125,10 +201,10 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
             .expect("redis getdel op to succeed");
 
         if !ready {
-            return OwnerConsent::Error("Passport not ready for auth!".to_string().into());
+            return refuse(&db, &audit, Failure, "Passport not ready for auth!").await;
         }
 
-        // If the user is an admin or has a 2FA code attached, require it here
+        // If the user is an admin or has a second factor set up, require it here
         let user: user::Model = passport
             .find_related(User)
             .one(&db)
@@ -136,38 +212,81 @@ impl OwnerSolicitor<RequestCompat> for AuthorizeSolicitor {
             .expect("db op to succeed")
             .expect("Passport to have an owner");
 
-        // Very basic scope control
-        if solicitation
-            .pre_grant()
-            .scope
-            .iter()
-            .any(|s| s.starts_with("admin"))
-            && user.role != RoleEnum::Admin
-        {
-            return OwnerConsent::Error(
-                "You may not access administrator scopes!"
-                    .to_string()
-                    .into(),
-            );
+        if user.disabled_at.is_some() {
+            return refuse(&db, &audit, Denied, "Account disabled!").await;
+        }
+
+        if !roles::may_grant(&user.role, &solicitation.pre_grant().scope) {
+            return refuse(
+                &db,
+                &audit,
+                Denied,
+                "Your role may not access these scopes!",
+            )
+            .await;
         }
 
-        if let Some(totp) = user.totp {
-            let code = url
-                .query_pairs()
-                .into_iter()
-                .find_map(|(k, v)| if k == "code" { Some(v) } else { None })
-                .expect("TOTP code to be given");
+        let enrolled = tfa::enrolled(&db, &user)
+            .await
+            .expect("second factor lookup to succeed");
+        let mut second_factor = None;
+        if enrolled {
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find_map(|(k, v)| if k == name { Some(v) } else { None })
+            };
 
-            if !tfa::validate_totp(user.id, totp, &code).expect("TOTP validation to succeed") {
-                return OwnerConsent::Error("Invalid TOTP code!".to_string().into());
+            // A passkey assertion answering the challenge from /api/webauthn, a code from the
+            // authenticator app, or one of the user's recovery codes
+            if let Some(assertion) = param("webauthn") {
+                let Ok(assertion) = serde_json::from_str::<PublicKeyCredential>(&assertion) else {
+                    return refuse(&db, &audit, Failure, "Invalid passkey response!").await;
+                };
+                if !webauthn::finish_authentication(&db, user.id, &assertion)
+                    .await
+                    .expect("passkey validation to succeed")
+                {
+                    return refuse(&db, &audit, Failure, "Invalid passkey response!").await;
+                }
+                second_factor = Some(step_up::PASSKEY);
+            } else if let Some(code) = param("code") {
+                if !tfa::verify(&db, &user, &code)
+                    .await
+                    .expect("TOTP validation to succeed")
+                {
+                    return refuse(&db, &audit, Failure, "Invalid TOTP code!").await;
+                }
+                second_factor = Some(step_up::TOTP);
+            } else {
+                return refuse(&db, &audit, Failure, "Second factor required!").await;
             }
-        } else if user.role == RoleEnum::Admin {
-            return OwnerConsent::Error("Admin login attempted without TOTP!".to_string().into());
+        } else if roles::requires_totp(&user.role)
+            && roles::needs_totp(&solicitation.pre_grant().scope)
+        {
+            // Still let them in for other scopes, so they can enrol
+            return refuse(
+                &db,
+                &audit,
+                Denied,
+                "Set up TOTP or a passkey before using admin scopes!",
+            )
+            .await;
         }
 
         if !user_wants_allow {
+            audit.record(&db, Denied).await;
             OwnerConsent::Denied
         } else {
+            let authentication = Authentication::tap(passport.id, second_factor);
+            audit
+                .clone()
+                .detail(json!({
+                    "scope": solicitation.pre_grant().scope.to_string(),
+                    "amr": authentication.amr,
+                }))
+                .success(&db)
+                .await;
+            *self.authentication.lock().expect("lock not to be poisoned") = Some(authentication);
             OwnerConsent::Authorized(passport.owner_id.to_string())
         }
     }
@@ -178,8 +297,13 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         return handle_get(req).await;
     }
 
+    let db = db().await?;
+    let authentication = Arc::new(Mutex::new(None));
+
     let mut res = AuthorizationFlow::prepare(OAuthEndpoint::new(
-        AuthorizeSolicitor,
+        AuthorizeSolicitor {
+            authentication: authentication.clone(),
+        },
         vec!["user".parse().expect("scope to parse")],
     ))
     .map_err(|e| format!("Auth prep error: {e}"))?
@@ -193,8 +317,6 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         let url = Url::parse(loc.to_str().unwrap()).unwrap();
         if let Some((_, grant)) = url.query_pairs().find(|(k, _)| k == "code") {
             // Grant given, reverse reference to user and create a session token
-            let db = db().await.unwrap();
-
             let grant: auth_grant::Model = AuthGrant::find()
                 .filter(auth_grant::Column::Code.eq(grant.as_ref()))
                 .one(&db)
@@ -202,11 +324,29 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
                 .unwrap()
                 .expect("grant to exist");
 
+            let authentication = authentication
+                .lock()
+                .expect("lock not to be poisoned")
+                .take()
+                .expect("consent to record how the user authenticated");
+            let amr = serde_json::to_value(&authentication.amr)?;
+
+            // Remember which passport this came from so it can be revoked if lost, and when
+            // and how the user logged in for the token's claims
+            let mut am = grant.clone().into_active_model();
+            am.passport_id = ActiveValue::Set(authentication.passport_id);
+            am.auth_time = ActiveValue::Set(Some(authentication.auth_time));
+            am.amr = ActiveValue::Set(Some(amr.clone()));
+            am.update(&db).await?;
+
             let new = auth_session::ActiveModel {
                 id: ActiveValue::NotSet,
                 token: ActiveValue::Set(Alphanumeric.sample_string(&mut rand::thread_rng(), 32)),
                 until: ActiveValue::Set((Utc::now() + Months::new(2)).into()),
                 owner_id: ActiveValue::Set(grant.owner_id),
+                passport_id: ActiveValue::Set(authentication.passport_id),
+                auth_time: ActiveValue::Set(authentication.auth_time),
+                amr: ActiveValue::Set(amr),
             };
 
             let model = new.insert(&db).await.expect("insert token");
@@ -234,16 +374,19 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
 }
 
 async fn handle_get(req: Request) -> Result<Response<Body>, Error> {
-    let res = AuthorizationFlow::prepare(OAuthEndpoint::new(
-        FnSolicitor(move |req: &mut RequestCompat, pre_grant: Solicitation| {
-            let has_session = req
-                .headers()
-                .get_all(COOKIE)
-                .iter()
-                .any(|v| v.to_str().unwrap_or_default().contains("session="));
+    let db = db().await?;
+    let auth_time = session(&db, &req).await?.map(|s| s.auth_time);
 
+    let res = AuthorizationFlow::prepare(OAuthEndpoint::new(
+        FnSolicitor(move |_: &mut RequestCompat, pre_grant: Solicitation| {
             let mut resp = ResponseCompat::default();
             let pg = pre_grant.pre_grant();
+
+            // Only offer the session if it is recent enough for what is being asked for
+            let has_session = auth_time.is_some_and(|t| {
+                !step_up::required(pg.client_id.as_ref(), &pg.scope) || step_up::is_fresh(&t)
+            });
+
             let client_id = pg.client_id.to_string();
             let redirect_uri = pg.redirect_uri.to_string();
             let scope = pg.scope.to_string();
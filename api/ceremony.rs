@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use entity::{ceremonies, ceremony_waitlist, prelude::*};
+use id::{audit, ceremony, db, roles, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, QueryOrder, QuerySelect};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct NewCeremony {
+    ceremony_time: String,
+    capacity: i32,
+    #[serde(default)]
+    name: String,
+    location: Option<String>,
+    #[serde(default)]
+    open_registration: bool,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+/// Lists upcoming ceremonies that are taking registrations, along with how many seats are left
+pub async fn get_handler(_req: Request) -> Result<Response<Body>, Error> {
+    let db = db().await?;
+
+    let upcoming: Vec<ceremonies::Model> = Ceremonies::find()
+        .filter(ceremonies::Column::CeremonyTime.gt(chrono::Utc::now().naive_utc()))
+        .filter(ceremonies::Column::OpenRegistration.eq(true))
+        .filter(ceremonies::Column::CancelledAt.is_null())
+        .order_by_asc(ceremonies::Column::CeremonyTime)
+        .all(&db)
+        .await?;
+
+    let ids: Vec<i32> = upcoming.iter().map(|c| c.id).collect();
+    // Seats held by someone partway through registering aren't up for grabs either
+    let seats = ceremony::seats_in_use(&db, &ids).await?;
+    let waitlists: HashMap<i32, i64> = CeremonyWaitlist::find()
+        .select_only()
+        .column(ceremony_waitlist::Column::CeremonyId)
+        .column_as(ceremony_waitlist::Column::Id.count(), "waitlisted")
+        .filter(ceremony_waitlist::Column::CeremonyId.is_in(ids))
+        .group_by(ceremony_waitlist::Column::CeremonyId)
+        .into_tuple()
+        .all(&db)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut listing = Vec::with_capacity(upcoming.len());
+    for c in upcoming {
+        let taken = seats.get(&c.id).copied().unwrap_or_default();
+        let waitlisted = waitlists.get(&c.id).copied().unwrap_or_default();
+        listing.push(json!({
+            "id": c.id,
+            "name": c.name,
+            "location": c.location,
+            "ceremony_time": c.ceremony_time,
+            "capacity": c.capacity,
+            "remaining_slots": (c.capacity.max(0) as u64).saturating_sub(taken),
+            "waitlisted": waitlisted,
+        }));
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(json!(listing).to_string().into())?)
+}
+
+/// Schedules a new ceremony
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let new: NewCeremony = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    let ceremony_time = ceremony::parse_time(&new.ceremony_time)?;
+    if new.capacity < 0 {
+        return Err("Capacity can't be negative".to_string().into());
+    }
+
+    let audit = audit::Event::new(&req, "ceremony.create");
+    let user = match roles::require(req, roles::Permission::ManageCeremonies).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+
+    let created = ceremonies::ActiveModel {
+        id: ActiveValue::NotSet,
+        ceremony_time: ActiveValue::Set(ceremony_time),
+        capacity: ActiveValue::Set(new.capacity),
+        open_registration: ActiveValue::Set(new.open_registration),
+        cancelled_at: ActiveValue::Set(None),
+        name: ActiveValue::Set(new.name),
+        location: ActiveValue::Set(new.location),
+    }
+    .insert(&db)
+    .await?;
+    audit
+        .actor(user)
+        .subject("ceremony", created.id)
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&created)?.into())?)
+}
@@ -0,0 +1,197 @@
+use entity::{ceremonies, ceremony_waitlist, prelude::*};
+use id::{audit, ceremony, db, roles, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Changes to a ceremony. Opening and closing registration is done through
+/// `open_registration`, and a new `ceremony_time` reschedules it.
+#[derive(Debug, serde::Deserialize)]
+struct CeremonyEdit {
+    ceremony_time: Option<String>,
+    capacity: Option<i32>,
+    open_registration: Option<bool>,
+    name: Option<String>,
+    /// Set to `null` to clear it
+    #[serde(default, deserialize_with = "present")]
+    location: Option<Option<String>>,
+}
+
+/// Tells a field set to `null` apart from one that was left out
+fn present<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Option<String>>, D::Error> {
+    serde::Deserialize::deserialize(d).map(Some)
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .map_err(|e| format!("Invalid ceremony ID! {e}"))?;
+
+    match *req.method() {
+        Method::GET => get_handler(req, id).await,
+        Method::PATCH => patch_handler(req, id).await,
+        Method::DELETE => delete_handler(req, id).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+async fn find(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<Result<ceremonies::Model, Response<Body>>, Error> {
+    Ok(Ceremonies::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or_else(|| ceremony::Unavailable::NotFound.response()))
+}
+
+/// Shows a ceremony along with how many of its seats are spoken for
+pub async fn get_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let _user = match roles::require(req, roles::Permission::ManageCeremonies).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+    let found = match find(&db, id).await? {
+        Ok(c) => c,
+        Err(resp) => return Ok(resp),
+    };
+
+    let taken = ceremony::seats_taken(&db, id, None).await?;
+    let held = ceremony::seats_held(id, None).await?;
+    let waitlist: Vec<ceremony_waitlist::Model> = CeremonyWaitlist::find()
+        .filter(ceremony_waitlist::Column::CeremonyId.eq(id))
+        .order_by_asc(ceremony_waitlist::Column::Id)
+        .all(&db)
+        .await?;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "ceremony": found,
+                "seats_taken": taken,
+                "seats_held": held,
+                "waitlist": waitlist,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
+
+/// Edits, opens, closes or reschedules a ceremony
+pub async fn patch_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let edit: CeremonyEdit = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    let new_time = edit
+        .ceremony_time
+        .as_deref()
+        .map(ceremony::parse_time)
+        .transpose()?;
+    if edit.capacity.is_some_and(|s| s < 0) {
+        return Err("Capacity can't be negative".to_string().into());
+    }
+    let audit = audit::Event::new(&req, "ceremony.update").subject("ceremony", id);
+
+    let user = match roles::require(req, roles::Permission::ManageCeremonies).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+    let found = match find(&db, id).await? {
+        Ok(c) => c,
+        Err(resp) => return Ok(resp),
+    };
+
+    if found.cancelled_at.is_some() {
+        return Ok(ceremony::Unavailable::Cancelled.response());
+    }
+
+    let mut am = found.into_active_model();
+    if let Some(capacity) = edit.capacity {
+        am.capacity = ActiveValue::Set(capacity);
+    }
+    if let Some(new_time) = new_time {
+        am.ceremony_time = ActiveValue::Set(new_time);
+    }
+    if let Some(name) = edit.name {
+        am.name = ActiveValue::Set(name);
+    }
+    if let Some(location) = edit.location {
+        am.location = ActiveValue::Set(location);
+    }
+    if let Some(open_registration) = edit.open_registration {
+        am.open_registration = ActiveValue::Set(open_registration);
+    }
+    let updated = am.update(&db).await?;
+    audit
+        .actor(user)
+        .detail(json!({
+            "capacity": updated.capacity,
+            "ceremony_time": updated.ceremony_time,
+            "open_registration": updated.open_registration,
+        }))
+        .success(&db)
+        .await;
+
+    // More seats may have opened up for the waitlist
+    ceremony::promote(&db, updated.id).await?;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&updated)?.into())?)
+}
+
+/// Cancels a ceremony, leaving everyone registered for it unassigned
+pub async fn delete_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "ceremony.cancel").subject("ceremony", id);
+    let admin = match roles::require(req, roles::Permission::ManageCeremonies).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+    let found = match find(&db, id).await? {
+        Ok(c) => c,
+        Err(resp) => return Ok(resp),
+    };
+
+    if found.cancelled_at.is_some() {
+        let mut resp = ceremony::Unavailable::Cancelled.response();
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
+    }
+
+    let unassigned = ceremony::cancel(&db, found, Some(admin)).await?;
+    audit
+        .actor(admin)
+        .detail(json!({ "unassigned": unassigned }))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "id": id,
+                "unassigned": unassigned,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use entity::{ceremony_attendance, passport, prelude::*, sea_orm_active_enums::PassportStatusEnum};
+use id::{audit, ceremony, db, roles, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, QueryOrder};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CheckIn {
+    passport_id: i32,
+}
+
+/// A registrant as shown to ceremony staff, without the passport secret
+#[derive(Debug, serde::Serialize)]
+struct Registrant {
+    id: i32,
+    owner_id: i32,
+    name: String,
+    surname: String,
+    status: PassportStatusEnum,
+    checked_in_at: Option<DateTimeWithTimeZone>,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+fn conflict(message: &'static str, code: &'static str) -> Result<Response<Body>, Error> {
+    let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+        message,
+        code,
+    })?));
+    *resp.status_mut() = StatusCode::CONFLICT;
+    Ok(resp)
+}
+
+/// Checks a passport in at the ceremony it's registered for
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let check_in: CheckIn = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    let audit =
+        audit::Event::new(&req, "ceremony.check_in").subject("passport", check_in.passport_id);
+
+    let staff = match roles::require(req, roles::Permission::CheckIn).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+
+    let Some(passport) = Passport::find_by_id(check_in.passport_id).one(&db).await? else {
+        let mut resp = Response::new(Body::Text("Passport does not exist".to_string()));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    };
+
+    let Some(ceremony_id) = passport.ceremony_id else {
+        return conflict(
+            "This passport isn't registered for a ceremony",
+            "not_registered",
+        );
+    };
+    if !matches!(
+        passport.status,
+        PassportStatusEnum::PendingCeremony | PassportStatusEnum::Issued
+    ) {
+        return conflict(
+            "This passport isn't waiting on a ceremony",
+            "not_pending_ceremony",
+        );
+    }
+
+    let registered = Ceremonies::find_by_id(ceremony_id)
+        .one(&db)
+        .await?
+        .ok_or("Ceremony does not exist".to_string())?;
+    if registered.cancelled_at.is_some() {
+        return Ok(ceremony::Unavailable::Cancelled.response());
+    }
+
+    let attendance = ceremony::check_in(&db, &passport, ceremony_id, Some(staff)).await?;
+    audit
+        .actor(staff)
+        .detail(json!({ "ceremony_id": ceremony_id }))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&attendance)?.into())?)
+}
+
+/// Reports who turned up to a ceremony and who didn't
+pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
+    let url = url::Url::parse(&req.uri().to_string())?;
+    let ceremony_id: i32 = url
+        .query_pairs()
+        .find_map(|(k, v)| if k == "ceremony_id" { Some(v) } else { None })
+        .ok_or("Ceremony ID required".to_string())?
+        .parse()
+        .map_err(|e| format!("Invalid ceremony ID! {e}"))?;
+
+    let _user = match roles::require(req, roles::Permission::CheckIn).await? {
+        Ok(id) => id,
+        Err(resp) => return Ok(resp),
+    };
+
+    let db = db().await?;
+
+    if Ceremonies::find_by_id(ceremony_id)
+        .one(&db)
+        .await?
+        .is_none()
+    {
+        return Ok(ceremony::Unavailable::NotFound.response());
+    }
+
+    let checked_in: HashMap<i32, DateTimeWithTimeZone> = CeremonyAttendance::find()
+        .filter(ceremony_attendance::Column::CeremonyId.eq(ceremony_id))
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|a| (a.passport_id, a.checked_in_at))
+        .collect();
+
+    let registrants: Vec<passport::Model> = Passport::find()
+        .filter(passport::Column::CeremonyId.eq(ceremony_id))
+        .filter(
+            passport::Column::Status
+                .is_not_in([PassportStatusEnum::Draft, PassportStatusEnum::Revoked]),
+        )
+        .order_by_asc(passport::Column::Id)
+        .all(&db)
+        .await?;
+
+    let (attended, no_shows): (Vec<Registrant>, Vec<Registrant>) = registrants
+        .into_iter()
+        .map(|p| Registrant {
+            checked_in_at: checked_in.get(&p.id).copied(),
+            id: p.id,
+            owner_id: p.owner_id,
+            name: p.name,
+            surname: p.surname,
+            status: p.status,
+        })
+        .partition(|r| r.checked_in_at.is_some());
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "ceremony_id": ceremony_id,
+                "attended": attended,
+                "no_shows": no_shows,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
@@ -0,0 +1,70 @@
+use id::{audit, ceremony, db, discord, wrap_error};
+use lambda_http::http::{header::AUTHORIZATION, Method};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HoldRequest {
+    ceremony_id: Option<i32>,
+    /// Older clients pick their ceremony by time
+    ceremony_time: Option<String>,
+}
+
+/// Keeps a seat at a ceremony for the caller while they finish designing their passport
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    if req.method() != Method::POST {
+        return Err("Invalid method".to_string().into());
+    }
+
+    let hold: HoldRequest = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    let audit = audit::Event::new(&req, "ceremony.hold");
+
+    let user_id = if req.headers().contains_key(AUTHORIZATION) {
+        audit
+            .oauth_user(req, vec!["user".parse().expect("scope to parse")])
+            .await?
+    } else if let Some(user_id) = discord::linked_user(&req).await? {
+        user_id
+    } else {
+        let mut resp = Response::new(Body::Text("Authorization required".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    };
+
+    let db = db().await?;
+
+    let ceremony_id =
+        match ceremony::resolve(&db, hold.ceremony_id, hold.ceremony_time.as_deref()).await? {
+            Ok(id) => id,
+            Err(unavailable) => return Ok(unavailable.response()),
+        };
+
+    let audit = audit.actor(user_id).subject("ceremony", ceremony_id);
+    match ceremony::hold(&db, ceremony_id, user_id).await? {
+        Ok(until) => {
+            audit.success(&db).await;
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(
+                    json!({
+                        "ceremony_id": ceremony_id,
+                        "held_until": until,
+                    })
+                    .to_string()
+                    .into(),
+                )?)
+        }
+        Err(unavailable) => {
+            audit.failure(&db).await;
+            Ok(unavailable.response())
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use id::{audit, db, discord, session, wrap_error, APIError};
+use lambda_http::http::header::{LOCATION, SET_COOKIE};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Links a Discord account to whoever is logged in, or finds its user for someone who isn't.
+/// Without a `code` this sends the browser off to Discord, and Discord sends it back here
+/// with one.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let url = url::Url::parse(&req.uri().to_string())?;
+    let param = |name: &str| {
+        url.query_pairs().find_map(|(k, v)| {
+            if k == name {
+                Some(v.into_owned())
+            } else {
+                None
+            }
+        })
+    };
+
+    let audit = audit::Event::new(&req, "discord.link");
+
+    if let Some(error) = param("error") {
+        let db = db().await?;
+        audit.detail(json!({ "error": error })).failure(&db).await;
+        let mut resp = Response::new(Body::Text(format!("Discord refused to link: {error}")));
+        *resp.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(resp);
+    }
+
+    let Some(code) = param("code") else {
+        let (url, state) = discord::authorize_url().await?;
+        return Ok(Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, url.to_string())
+            .header(
+                SET_COOKIE,
+                format!(
+                    "{}={state}; Max-Age={}; Secure; HttpOnly; SameSite=Lax; Path=/api/discord",
+                    discord::STATE_COOKIE,
+                    discord::STATE_TTL
+                ),
+            )
+            .body(Body::Empty)?);
+    };
+    let state = param("state").ok_or("No state provided!".to_string())?;
+
+    let profile = discord::verify(&req, &code, &state).await?;
+
+    let db = db().await?;
+    let owner_id = session(&db, &req).await?.map(|s| s.owner_id);
+    let Some(user) = discord::link(&db, profile, owner_id).await? else {
+        let audit = match owner_id {
+            Some(owner_id) => audit.actor(owner_id),
+            None => audit,
+        };
+        audit.failure(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "That Discord account is already linked to someone else",
+            code: "discord_taken",
+        })?));
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
+    };
+    audit
+        .actor(user.id)
+        .detail(json!({ "discord_id": user.discord_id }))
+        .success(&db)
+        .await;
+
+    let token = discord::issue_link_token(&user).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(LOCATION, "https://id.purduehackers.com/?discord=linked")
+        .header(
+            SET_COOKIE,
+            format!(
+                "{}={token}; Max-Age={}; Secure; HttpOnly; SameSite=Lax; Path=/",
+                discord::LINK_COOKIE,
+                discord::LINK_TTL
+            ),
+        )
+        .header(
+            SET_COOKIE,
+            format!(
+                "{}=; Max-Age=0; Secure; HttpOnly; SameSite=Lax; Path=/api/discord",
+                discord::STATE_COOKIE
+            ),
+        )
+        .body(Body::Empty)?)
+}
@@ -1,7 +1,9 @@
-use entity::passport;
+use chrono::Utc;
 use entity::prelude::*;
-use id::{db, wrap_error, PassportRecord};
+use entity::{passport, sea_orm_active_enums::DoorResultEnum, user};
+use id::{audit, db, door, lifecycle, wrap_error, PassportRecord};
 use sea_orm::prelude::*;
+use serde_json::json;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
@@ -9,38 +11,87 @@ async fn main() -> Result<(), Error> {
     run(wrap_error!(handler)).await
 }
 
+/// Opens the door for a passport tapped on a registered door reader. Readers sign each request
+/// as `door::authenticate` describes, so that nobody else can use this to guess secrets.
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let db = db().await?;
+    let reader = match door::authenticate(&db, &req).await? {
+        Ok(reader) => reader,
+        Err(resp) => return Ok(resp),
+    };
+
     match req.body() {
         Body::Text(_) | Body::Empty => Err("Invalid body".to_string().into()),
         Body::Binary(b) => {
             let record: PassportRecord = serde_json::from_str(&String::from_utf8(b.to_vec())?)?;
+            let audit = audit::Event::new(&req, "door.open").subject("passport", record.id);
+            let reader_id = Some(reader.slug);
 
             // Check if the passport exists and is valid
-            let db = db().await?;
             let passport: Option<passport::Model> =
                 Passport::find_by_id(record.id).one(&db).await?;
 
-            match passport {
+            let (owner_id, verdict) = match passport {
                 Some(passport) => {
-                    if !passport.activated {
-                        let mut resp = Response::new(Body::Text("Passport disabled".to_string()));
-                        *resp.status_mut() = StatusCode::FORBIDDEN;
-                        Ok(resp)
-                    } else if passport.secret != record.secret {
-                        let mut resp =
-                            Response::new(Body::Text("Passport secret incorrect".to_string()));
-                        *resp.status_mut() = StatusCode::UNAUTHORIZED;
-                        Ok(resp)
+                    let passport = lifecycle::expire_if_due(&db, passport).await?;
+                    let owner: Option<user::Model> = passport.find_related(User).one(&db).await?;
+                    let verdict = if let Some(refusal) = lifecycle::refusal(&passport.status) {
+                        door::Verdict::new(DoorResultEnum::InactivePassport, refusal)
+                    } else if let Some(owner) = owner.filter(|o| o.disabled_at.is_none()) {
+                        if passport.secret != record.secret {
+                            door::Verdict::new(
+                                DoorResultEnum::WrongSecret,
+                                "Passport secret incorrect",
+                            )
+                        } else {
+                            door::decide(&db, &owner, reader_id.as_deref(), Utc::now()).await?
+                        }
                     } else {
-                        Ok(Response::new(Body::Empty))
-                    }
+                        door::Verdict::new(DoorResultEnum::AccountDisabled, "Account disabled")
+                    };
+                    (Some(passport.owner_id), verdict)
+                }
+                None => (
+                    None,
+                    door::Verdict::new(DoorResultEnum::UnknownPassport, "Passport does not exist"),
+                ),
+            };
+
+            door::log(&db, record.id, owner_id, reader_id.clone(), &verdict).await?;
+
+            let audit = match owner_id {
+                Some(owner_id) => audit.actor(owner_id),
+                None => audit,
+            };
+            let audit = audit.detail(json!({
+                "reader_id": reader_id,
+                "rule_id": verdict.rule_id,
+                "reason": verdict.reason,
+            }));
+            let status = match verdict.result {
+                DoorResultEnum::Opened => {
+                    audit.success(&db).await;
+                    StatusCode::OK
                 }
-                None => {
-                    let mut resp = Response::new(Body::Text("Passport does not exist".to_string()));
-                    *resp.status_mut() = StatusCode::NOT_FOUND;
-                    Ok(resp)
+                DoorResultEnum::UnknownPassport => {
+                    audit.failure(&db).await;
+                    StatusCode::NOT_FOUND
                 }
-            }
+                DoorResultEnum::WrongSecret => {
+                    audit.failure(&db).await;
+                    StatusCode::UNAUTHORIZED
+                }
+                DoorResultEnum::InactivePassport
+                | DoorResultEnum::AccountDisabled
+                | DoorResultEnum::NoAccess => {
+                    audit.denied(&db).await;
+                    StatusCode::FORBIDDEN
+                }
+            };
+
+            let mut resp = Response::new(Body::Text(verdict.reason.unwrap_or_default()));
+            *resp.status_mut() = status;
+            Ok(resp)
         }
     }
 }
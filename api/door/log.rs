@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use entity::{door_access, prelude::*, sea_orm_active_enums::DoorResultEnum};
+use id::{audit, db, door, param, wrap_error};
+use sea_orm::prelude::*;
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Lists door taps, newest first, to answer questions like who was in the lab last night.
+///
+/// `since` and `until` are RFC 3339 times, and `passport_id`, `owner_id`, `reader_id` and
+/// `result` narrow it down further. Pass the `next_cursor` from one page as `cursor` to get
+/// the next.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+    let audit = audit::Event::new(&req, "door.log").detail(json!(params));
+
+    let admin = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let mut query = DoorAccess::find();
+    if let Some(passport_id) = param::<i32>(&params, "passport_id")? {
+        query = query.filter(door_access::Column::PassportId.eq(passport_id));
+    }
+    if let Some(owner_id) = param::<i32>(&params, "owner_id")? {
+        query = query.filter(door_access::Column::OwnerId.eq(owner_id));
+    }
+    if let Some(reader_id) = params.get("reader_id") {
+        query = query.filter(door_access::Column::ReaderId.eq(reader_id));
+    }
+    if let Some(result) = params.get("result") {
+        let result: DoorResultEnum =
+            serde_json::from_value(serde_json::Value::String(result.to_string()))
+                .map_err(|e| format!("Unknown result! {e}"))?;
+        query = query.filter(door_access::Column::Result.eq(result));
+    }
+    let (query, limit) = door::window(query, &params)?;
+
+    let db = db().await?;
+    let (entries, next_cursor) = door::page(&db, query, limit).await?;
+    audit.actor(admin).success(&db).await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "entries": entries,
+                "next_cursor": next_cursor,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
@@ -0,0 +1,133 @@
+use entity::{door_reader, prelude::*};
+use id::{audit, db, door, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, QueryOrder, SqlErr};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct NewReader {
+    /// Short name the reader signs requests and door rules refer to it with, like `lab-front`
+    slug: String,
+    name: Option<String>,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+/// Lists every door reader
+pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "door.reader.list");
+    let _user = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    let readers: Vec<serde_json::Value> = DoorReader::find()
+        .order_by_asc(door_reader::Column::Slug)
+        .all(&db)
+        .await?
+        .iter()
+        .map(door::reader_json)
+        .collect();
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(json!(readers).to_string().into())?)
+}
+
+fn reader_exists() -> Result<Response<Body>, Error> {
+    let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+        message: "There is already a reader with that slug",
+        code: "reader_exists",
+    })?));
+    *resp.status_mut() = StatusCode::CONFLICT;
+    Ok(resp)
+}
+
+/// Registers a door reader, answering with the secret it signs requests with. This is the
+/// only time the secret is shown.
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let new: NewReader = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    if new.slug.is_empty()
+        || !new
+            .slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(
+            "Slugs may only contain lowercase letters, digits and dashes"
+                .to_string()
+                .into(),
+        );
+    }
+
+    let audit = audit::Event::new(&req, "door.reader.create");
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    if DoorReader::find()
+        .filter(door_reader::Column::Slug.eq(&new.slug))
+        .one(&db)
+        .await?
+        .is_some()
+    {
+        return reader_exists();
+    }
+
+    let (secret, sealed) = door::new_reader_secret(&new.slug)?;
+    let created = match (door_reader::ActiveModel {
+        id: ActiveValue::NotSet,
+        slug: ActiveValue::Set(new.slug),
+        name: ActiveValue::Set(new.name),
+        secret: ActiveValue::Set(sealed),
+        created_at: ActiveValue::NotSet,
+        last_seen_at: ActiveValue::Set(None),
+        disabled_at: ActiveValue::Set(None),
+    })
+    .insert(&db)
+    .await
+    {
+        Ok(created) => created,
+        // Another request took the slug since we checked
+        Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+            return reader_exists();
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    audit
+        .actor(admin)
+        .subject("door_reader", &created.slug)
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "reader": door::reader_json(&created),
+                "secret": secret,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
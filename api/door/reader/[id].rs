@@ -0,0 +1,137 @@
+use chrono::Utc;
+use entity::prelude::*;
+use id::{audit, db, door, notify_admins, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Changes an admin can make to a door reader
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ReaderEdit {
+    name: Option<String>,
+    disabled: Option<bool>,
+    /// Replaces the reader's secret, which has to be put on the device again
+    rotate_secret: bool,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .map_err(|e| format!("Invalid reader ID! {e}"))?;
+
+    match *req.method() {
+        Method::PATCH => patch_handler(req, id).await,
+        Method::DELETE => delete_handler(req, id).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+fn not_found() -> Response<Body> {
+    let mut resp = Response::new(Body::Text("Reader does not exist".to_string()));
+    *resp.status_mut() = StatusCode::NOT_FOUND;
+    resp
+}
+
+/// Renames, disables or re-enables a reader, or gives it a new secret. A new secret is only
+/// shown in this response.
+pub async fn patch_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let edit: ReaderEdit = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "door.reader.update");
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(reader) = DoorReader::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    let audit = audit.subject("door_reader", &reader.slug);
+
+    let mut am = reader.clone().into_active_model();
+    if let Some(name) = edit.name.clone() {
+        am.name = ActiveValue::Set(Some(name));
+    }
+    match edit.disabled {
+        Some(true) if reader.disabled_at.is_none() => {
+            am.disabled_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+        }
+        Some(false) => am.disabled_at = ActiveValue::Set(None),
+        _ => {}
+    }
+    let mut secret = None;
+    if edit.rotate_secret {
+        let (plain, sealed) = door::new_reader_secret(&reader.slug)?;
+        am.secret = ActiveValue::Set(sealed);
+        secret = Some(plain);
+    }
+    let updated = am.update(&db).await?;
+
+    audit
+        .actor(admin)
+        .detail(json!({
+            "name": edit.name,
+            "disabled": edit.disabled,
+            "rotate_secret": edit.rotate_secret,
+        }))
+        .success(&db)
+        .await;
+    if edit.disabled == Some(true) && reader.disabled_at.is_none() {
+        notify_admins(&format!(
+            "Door reader {} was disabled by admin {admin}.",
+            updated.slug
+        ))
+        .await;
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "reader": door::reader_json(&updated),
+                "secret": secret,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
+
+/// Removes a reader. Its door log entries and rules keep its slug.
+pub async fn delete_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "door.reader.delete");
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(reader) = DoorReader::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    let slug = reader.slug.clone();
+    reader.delete(&db).await?;
+
+    audit
+        .actor(admin)
+        .subject("door_reader", slug)
+        .success(&db)
+        .await;
+
+    Ok(Response::new(Body::Empty))
+}
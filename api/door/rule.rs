@@ -0,0 +1,83 @@
+use entity::{door_rule, prelude::*};
+use id::{audit, db, door, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, QueryOrder};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+/// Lists door access rules in the order they are tried
+pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "door.rule.list");
+    let _user = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    let rules: Vec<serde_json::Value> = DoorRule::find()
+        .order_by_asc(door_rule::Column::Priority)
+        .order_by_asc(door_rule::Column::Id)
+        .all(&db)
+        .await?
+        .iter()
+        .map(door::rule_json)
+        .collect();
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(json!(rules).to_string().into())?)
+}
+
+/// Adds a door access rule
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let spec: door::RuleSpec = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "door.rule.create");
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    if let Some(group_id) = spec.group_id {
+        if UserGroup::find_by_id(group_id).one(&db).await?.is_none() {
+            return Err(format!("Group {group_id} does not exist").into());
+        }
+    }
+
+    let mut am = door_rule::ActiveModel {
+        id: ActiveValue::NotSet,
+        created_at: ActiveValue::NotSet,
+        ..Default::default()
+    };
+    spec.apply(&mut am);
+    let created = am.insert(&db).await?;
+
+    audit
+        .actor(admin)
+        .subject("door_rule", created.id)
+        .detail(door::rule_json(&created))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(door::rule_json(&created).to_string().into())?)
+}
@@ -0,0 +1,91 @@
+use entity::{door_rule, prelude::*};
+use id::{audit, db, door, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, IntoActiveModel};
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .map_err(|e| format!("Invalid rule ID! {e}"))?;
+
+    match *req.method() {
+        Method::PUT => put_handler(req, id).await,
+        Method::DELETE => delete_handler(req, id).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+fn not_found() -> Response<Body> {
+    let mut resp = Response::new(Body::Text("Rule does not exist".to_string()));
+    *resp.status_mut() = StatusCode::NOT_FOUND;
+    resp
+}
+
+/// Replaces a rule with a new one, keeping its id
+pub async fn put_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let spec: door::RuleSpec = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "door.rule.update").subject("door_rule", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(rule) = DoorRule::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    if let Some(group_id) = spec.group_id {
+        if UserGroup::find_by_id(group_id).one(&db).await?.is_none() {
+            return Err(format!("Group {group_id} does not exist").into());
+        }
+    }
+
+    let mut am: door_rule::ActiveModel = rule.into_active_model();
+    spec.apply(&mut am);
+    let updated = am.update(&db).await?;
+
+    audit
+        .actor(admin)
+        .detail(door::rule_json(&updated))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(door::rule_json(&updated).to_string().into())?)
+}
+
+/// Removes a rule. Door log entries it decided keep their reason but lose the link to it.
+pub async fn delete_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "door.rule.delete").subject("door_rule", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(rule) = DoorRule::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    let removed = door::rule_json(&rule);
+    rule.delete(&db).await?;
+
+    audit.actor(admin).detail(removed).success(&db).await;
+
+    Ok(Response::new(Body::Empty))
+}
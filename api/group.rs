@@ -0,0 +1,123 @@
+use entity::{group_membership, prelude::*, user_group};
+use id::{audit, db, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, PaginatorTrait, QueryOrder};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct NewGroup {
+    /// Short name that shows up in the `groups` claim, like `design-team`
+    slug: String,
+    name: String,
+    description: Option<String>,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+/// Lists every group along with how many members it has
+pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "group.list");
+    let _user = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    let all_groups: Vec<user_group::Model> = UserGroup::find()
+        .order_by_asc(user_group::Column::Slug)
+        .all(&db)
+        .await?;
+
+    let mut listing = Vec::with_capacity(all_groups.len());
+    for g in all_groups {
+        let members = GroupMembership::find()
+            .filter(group_membership::Column::GroupId.eq(g.id))
+            .count(&db)
+            .await?;
+        listing.push(json!({
+            "id": g.id,
+            "slug": g.slug,
+            "name": g.name,
+            "description": g.description,
+            "members": members,
+        }));
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(json!(listing).to_string().into())?)
+}
+
+/// Creates a group
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let new: NewGroup = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+    if new.slug.is_empty()
+        || !new
+            .slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(
+            "Slugs may only contain lowercase letters, digits and dashes"
+                .to_string()
+                .into(),
+        );
+    }
+
+    let audit = audit::Event::new(&req, "group.create");
+    let user = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+
+    if UserGroup::find()
+        .filter(user_group::Column::Slug.eq(&new.slug))
+        .one(&db)
+        .await?
+        .is_some()
+    {
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "There is already a group with that slug",
+            code: "group_exists",
+        })?));
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
+    }
+
+    let created = user_group::ActiveModel {
+        id: ActiveValue::NotSet,
+        slug: ActiveValue::Set(new.slug),
+        name: ActiveValue::Set(new.name),
+        description: ActiveValue::Set(new.description),
+        created_at: ActiveValue::Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(&db)
+    .await?;
+    audit
+        .actor(user)
+        .subject("group", created.id)
+        .detail(json!({ "slug": created.slug }))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&created)?.into())?)
+}
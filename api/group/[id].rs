@@ -0,0 +1,186 @@
+use entity::{group_membership, prelude::*, user, user_group};
+use id::{audit, db, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder, TransactionTrait};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Changes to a group and who is in it
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct GroupEdit {
+    name: Option<String>,
+    description: Option<String>,
+    /// User ids to add to the group
+    add: Vec<i32>,
+    /// User ids to take out of the group
+    remove: Vec<i32>,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .map_err(|e| format!("Invalid group ID! {e}"))?;
+
+    match *req.method() {
+        Method::GET => get_handler(req, id).await,
+        Method::PATCH => patch_handler(req, id).await,
+        Method::DELETE => delete_handler(req, id).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+fn not_found() -> Response<Body> {
+    let mut resp = Response::new(Body::Text("Group does not exist".to_string()));
+    *resp.status_mut() = StatusCode::NOT_FOUND;
+    resp
+}
+
+async fn group_json(
+    db: &DatabaseConnection,
+    group: &user_group::Model,
+) -> Result<serde_json::Value, Error> {
+    let members: Vec<serde_json::Value> = User::find()
+        .inner_join(GroupMembership)
+        .filter(group_membership::Column::GroupId.eq(group.id))
+        .order_by_asc(user::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| {
+            json!({
+                "id": u.id,
+                "discord_id": u.discord_id,
+                "discord_username": u.discord_username,
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "id": group.id,
+        "slug": group.slug,
+        "name": group.name,
+        "description": group.description,
+        "members": members,
+    }))
+}
+
+/// Shows a group and its members
+pub async fn get_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "group.view");
+    let _user = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(group) = UserGroup::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(group_json(&db, &group).await?.to_string().into())?)
+}
+
+/// Renames a group or changes who is in it
+pub async fn patch_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let edit: GroupEdit = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "group.update").subject("group", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(group) = UserGroup::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    let txn = db.begin().await?;
+
+    let mut am = group.into_active_model();
+    if let Some(name) = edit.name {
+        am.name = ActiveValue::Set(name);
+    }
+    if let Some(description) = edit.description {
+        am.description = ActiveValue::Set(Some(description));
+    }
+    let group = am.update(&txn).await?;
+
+    if !edit.remove.is_empty() {
+        GroupMembership::delete_many()
+            .filter(group_membership::Column::GroupId.eq(group.id))
+            .filter(group_membership::Column::UserId.is_in(edit.remove.clone()))
+            .exec(&txn)
+            .await?;
+    }
+    for user_id in &edit.add {
+        let already = GroupMembership::find()
+            .filter(group_membership::Column::GroupId.eq(group.id))
+            .filter(group_membership::Column::UserId.eq(*user_id))
+            .one(&txn)
+            .await?;
+        if already.is_some() {
+            continue;
+        }
+        if User::find_by_id(*user_id).one(&txn).await?.is_none() {
+            return Err(format!("User {user_id} does not exist").into());
+        }
+
+        group_membership::ActiveModel {
+            id: ActiveValue::NotSet,
+            group_id: ActiveValue::Set(group.id),
+            user_id: ActiveValue::Set(*user_id),
+            added_at: ActiveValue::Set(chrono::Utc::now().fixed_offset()),
+        }
+        .insert(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+    audit
+        .actor(admin)
+        .detail(json!({ "add": edit.add, "remove": edit.remove }))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(group_json(&db, &group).await?.to_string().into())?)
+}
+
+/// Deletes a group, along with everyone's membership in it
+pub async fn delete_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "group.delete").subject("group", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(group) = UserGroup::find_by_id(id).one(&db).await? else {
+        return Ok(not_found());
+    };
+
+    let slug = group.slug.clone();
+    group.delete(&db).await?;
+    audit
+        .actor(admin)
+        .detail(json!({ "slug": slug }))
+        .success(&db)
+        .await;
+
+    Ok(Response::new(Body::Empty))
+}
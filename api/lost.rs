@@ -0,0 +1,157 @@
+use entity::{
+    auth_grant, auth_session, passport, prelude::*, sea_orm_active_enums::PassportStatusEnum,
+};
+use fred::prelude::*;
+use id::{audit, db, kv, lifecycle, notify_admins, session, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, QueryOrder, TransactionTrait};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Which passport went missing. Without an id, the owner's current passport is assumed.
+#[derive(Debug, Default, serde::Deserialize)]
+struct LostReport {
+    id: Option<i32>,
+    reason: Option<String>,
+}
+
+/// Lets a logged in hacker mark their passport as lost, cutting off everything it was used for
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    if req.method() != Method::POST {
+        return Err("Invalid method".to_string().into());
+    }
+
+    let audit = audit::Event::new(&req, "passport.lost");
+
+    let report: LostReport = match req.body() {
+        Body::Empty => LostReport::default(),
+        Body::Text(_) => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let db = db().await?;
+
+    let Some(session) = session(&db, &req).await? else {
+        let mut resp = Response::new(Body::Text("Not logged in".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    };
+
+    let passport: Option<passport::Model> = match report.id {
+        Some(id) => {
+            Passport::find_by_id(id)
+                .filter(passport::Column::OwnerId.eq(session.owner_id))
+                .one(&db)
+                .await?
+        }
+        None => {
+            Passport::find()
+                .filter(passport::Column::OwnerId.eq(session.owner_id))
+                .filter(
+                    passport::Column::Status
+                        .is_in([PassportStatusEnum::Active, PassportStatusEnum::Suspended]),
+                )
+                .order_by_desc(passport::Column::Id)
+                .one(&db)
+                .await?
+        }
+    };
+
+    let audit = audit.actor(session.owner_id);
+
+    let Some(passport) = passport else {
+        audit.failure(&db).await;
+        let mut resp = Response::new(Body::Text("Passport does not exist".to_string()));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    };
+
+    // Reporting it a second time has nothing left to revoke
+    if passport.status == PassportStatusEnum::Lost {
+        return Ok(Response::builder()
+            .header("Content-Type", "application/json")
+            .body(
+                json!({
+                    "id": passport.id,
+                    "status": passport.status,
+                    "revoked_sessions": 0,
+                    "revoked_grants": 0,
+                })
+                .to_string()
+                .into(),
+            )?);
+    }
+    if !lifecycle::can_transition(&passport.status, &PassportStatusEnum::Lost) {
+        audit.subject("passport", passport.id).failure(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "Only an active or suspended passport can be reported lost",
+            code: "invalid_status",
+        })?));
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
+    }
+
+    let txn = db.begin().await?;
+
+    // Counted up front, since the transition deletes them
+    let sessions = AuthSession::find()
+        .filter(auth_session::Column::PassportId.eq(passport.id))
+        .count(&txn)
+        .await?;
+    let grants = AuthGrant::find()
+        .filter(auth_grant::Column::PassportId.eq(passport.id))
+        .count(&txn)
+        .await?;
+
+    let passport = lifecycle::transition(
+        &txn,
+        passport,
+        PassportStatusEnum::Lost,
+        Some(
+            report
+                .reason
+                .unwrap_or_else(|| "reported lost by owner".to_string()),
+        ),
+        Some(session.owner_id),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    audit
+        .subject("passport", passport.id)
+        .detail(json!({
+            "revoked_sessions": sessions,
+            "revoked_grants": grants,
+        }))
+        .success(&db)
+        .await;
+
+    // Drop any scan that's halfway through logging in
+    let kv = kv().await?;
+    kv.del::<(), _>(passport.id).await?;
+
+    notify_admins(&format!(
+        "Passport {} belonging to user {} was reported lost. Revoked {} sessions and {} grants.",
+        passport.id, passport.owner_id, sessions, grants
+    ))
+    .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "id": passport.id,
+                "status": passport.status,
+                "revoked_sessions": sessions,
+                "revoked_grants": grants,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
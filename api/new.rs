@@ -3,14 +3,15 @@ use std::str::FromStr;
 use entity::{
     passport::{self},
     prelude::*,
-    sea_orm_active_enums::RoleEnum,
+    sea_orm_active_enums::{PassportStatusEnum, RoleEnum},
     user,
 };
-use id::{db, wrap_error};
+use id::{audit, ceremony, confidential_client, db, discord, lifecycle, wrap_error};
+use lambda_http::http::{header::AUTHORIZATION, Method};
 use rand::distributions::{Alphanumeric, DistString};
-use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder};
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder, TransactionTrait};
 use serde_json::json;
-use vercel_runtime::{run, Body, Error, Request, Response};
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -19,13 +20,19 @@ async fn main() -> Result<(), Error> {
 
 #[derive(Debug, serde::Deserialize)]
 struct NewPassport {
-    discord_id: String,
+    /// Only trusted from the issuing office; everyone else registers their linked account
+    discord_id: Option<String>,
     name: String,
     surname: String,
     date_of_birth: String,
     date_of_issue: String,
     place_of_origin: String,
-    ceremony_time: String,
+    ceremony_id: Option<i32>,
+    /// Older clients pick their ceremony by time
+    ceremony_time: Option<String>,
+    /// Join the waitlist if the ceremony is full instead of being turned away
+    #[serde(default)]
+    waitlist: bool,
 }
 
 const CURRENT_PASSPORT_VERSION: i32 = 1;
@@ -37,17 +44,24 @@ fn parse_date(s: &str) -> Result<ChronoDate, Error> {
     Ok(ChronoDateTime::parse_from_str(s, "%+")?.date())
 }
 
-fn parse_datetime(s: &str) -> Result<ChronoDateTime, Error> {
-    if let Ok(datetime) = ChronoDateTime::from_str(s) {
-        return Ok(datetime);
-    }
-    Ok(ChronoDateTime::parse_from_str(s, "%+")?)
+/// Rewrites the data page of a passport that hasn't been made yet
+fn update_data(passport: &mut passport::ActiveModel, new: NewPassport) -> Result<(), Error> {
+    passport.name = ActiveValue::Set(new.name);
+    passport.surname = ActiveValue::Set(new.surname);
+    passport.date_of_birth = ActiveValue::Set(parse_date(&new.date_of_birth)?);
+    passport.date_of_issue = ActiveValue::Set(parse_date(&new.date_of_issue)?);
+    passport.place_of_origin = ActiveValue::Set(new.place_of_origin);
+
+    Ok(())
 }
 
-async fn create_new_passport(
-    db: &DatabaseConnection,
+/// Creates a passport, waiting on its ceremony if it has one and a draft otherwise
+async fn create_new_passport<C: ConnectionTrait>(
+    db: &C,
     user: &user::Model,
     new: NewPassport,
+    ceremony_id: Option<i32>,
+    actor: Option<i32>,
 ) -> Result<passport::Model, Error> {
     let passport = passport::ActiveModel {
         id: ActiveValue::NotSet,
@@ -57,95 +71,419 @@ async fn create_new_passport(
         date_of_birth: ActiveValue::Set(parse_date(&new.date_of_birth)?),
         date_of_issue: ActiveValue::Set(parse_date(&new.date_of_issue)?),
         place_of_origin: ActiveValue::Set(new.place_of_origin),
-        ceremony_time: ActiveValue::Set(parse_datetime(&new.ceremony_time)?),
+        ceremony_id: ActiveValue::Set(ceremony_id),
         version: ActiveValue::Set(CURRENT_PASSPORT_VERSION),
-        activated: ActiveValue::Set(false),
         secret: ActiveValue::Set(Alphanumeric.sample_string(&mut rand::thread_rng(), 32)),
+        status: ActiveValue::Set(if ceremony_id.is_some() {
+            PassportStatusEnum::PendingCeremony
+        } else {
+            PassportStatusEnum::Draft
+        }),
+        status_changed_at: ActiveValue::Set(chrono::Utc::now().fixed_offset()),
     };
 
     let new_passport = passport.insert(db).await?;
+    lifecycle::record_initial(db, &new_passport, actor).await?;
 
     Ok(new_passport)
 }
 
-pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    match req.body() {
-        Body::Text(_) | Body::Empty => Err("Invalid body".to_string().into()),
-        Body::Binary(b) => {
-            let t = String::from_utf8(b.to_vec())
-                .map_err(|e| format!("Bad UTF-8 encoding! Couldn't convert to text: {e}"))?;
-            let new: NewPassport = serde_json::from_str(&t).map_err(|e| {
-                format!("Bad JSON encoding! Couldn't convert to passport data: [{e}]: {t}")
-            })?;
-            let discord_id = new
-                .discord_id
-                .parse()
-                .map_err(|e| format!("Couldn't parse Discord ID! [{e}] {}", new.discord_id))?;
-
-            let db = db().await?;
+/// Who is registering the passport
+#[derive(Debug)]
+enum Registrar {
+    /// A user acting on their own account with an OAuth token
+    User(i32),
+    /// A user who just verified their Discord account through the linking flow
+    Linked(i32),
+    /// A confidential client such as the issuing office
+    Client(&'static str),
+}
+
+impl Registrar {
+    /// Attributes an audit event to the registrar
+    fn audit(&self, event: audit::Event) -> audit::Event {
+        match *self {
+            Registrar::User(id) | Registrar::Linked(id) => event.actor(id),
+            Registrar::Client(client_id) => event.client(client_id),
+        }
+    }
+}
+
+/// Who is making the request, if anyone we recognise
+async fn registrar(req: Request, audit: &audit::Event) -> Result<Option<Registrar>, Error> {
+    Ok(if let Some(client) = confidential_client(&req) {
+        Some(Registrar::Client(client))
+    } else if req.headers().contains_key(AUTHORIZATION) {
+        Some(Registrar::User(
+            audit
+                .oauth_user(req, vec!["user".parse().expect("scope to parse")])
+                .await?,
+        ))
+    } else {
+        discord::linked_user(&req).await?.map(Registrar::Linked)
+    })
+}
 
+fn unauthorized() -> Response<Body> {
+    let mut resp = Response::new(Body::Text("Authorization required".to_string()));
+    *resp.status_mut() = StatusCode::UNAUTHORIZED;
+    resp
+}
+
+fn parse_discord_id(discord_id: Option<&str>) -> Result<Option<i64>, Error> {
+    Ok(discord_id
+        .map(|d| {
+            d.parse()
+                .map_err(|e| format!("Couldn't parse Discord ID! [{e}] {d}"))
+        })
+        .transpose()?)
+}
+
+/// The user whose passport is being registered, and who to record as making the change.
+/// The issuing office may register anyone, creating them if `create` is set; everyone
+/// else may only register themselves.
+async fn registrant(
+    db: &DatabaseConnection,
+    registrar: &Registrar,
+    discord_id: Option<i64>,
+    create: bool,
+) -> Result<Result<(user::Model, Option<i32>), Response<Body>>, Error> {
+    match *registrar {
+        Registrar::Client(_) => {
+            let discord_id = discord_id.ok_or("Discord ID required".to_string())?;
             let user: Option<user::Model> = User::find()
                 .filter(user::Column::DiscordId.eq(discord_id))
-                .one(&db)
+                .one(db)
                 .await?;
 
             let user = match user {
                 Some(u) => u,
-                None => {
+                None if create => {
                     let model = user::ActiveModel {
                         id: ActiveValue::NotSet,
                         discord_id: ActiveValue::Set(discord_id),
                         role: ActiveValue::Set(RoleEnum::Hacker),
-                        totp: ActiveValue::NotSet,
+                        ..Default::default()
                     };
 
-                    let user: user::Model = model.insert(&db).await?;
-
-                    user
+                    model.insert(db).await?
+                }
+                None => {
+                    let mut resp = Response::new(Body::Text("User not found".to_string()));
+                    *resp.status_mut() = StatusCode::NOT_FOUND;
+                    return Ok(Err(resp));
                 }
             };
 
-            let latest_passport = Passport::find()
-                .filter(passport::Column::OwnerId.eq(user.id))
-                .order_by_desc(passport::Column::Id)
-                .one(&db)
-                .await?;
+            Ok(Ok((user, None)))
+        }
+        Registrar::User(user_id) | Registrar::Linked(user_id) => {
+            let user: user::Model = User::find_by_id(user_id)
+                .one(db)
+                .await?
+                .ok_or("User not found".to_string())?;
 
-            let passport_id = match latest_passport {
+            if user.disabled_at.is_some() {
+                let mut resp = Response::new(Body::Text("Account disabled".to_string()));
+                *resp.status_mut() = StatusCode::FORBIDDEN;
+                return Ok(Err(resp));
+            }
+
+            // Passports are bound to the Discord account the user has proven they own
+            if user.discord_verified_at.is_none() {
+                let mut resp = Response::new(Body::Text(
+                    "Link your Discord account before registering a passport".to_string(),
+                ));
+                *resp.status_mut() = StatusCode::FORBIDDEN;
+                return Ok(Err(resp));
+            }
+            if discord_id.is_some_and(|d| d != user.discord_id) {
+                let mut resp = Response::new(Body::Text(
+                    "You may only register passports for your own Discord account".to_string(),
+                ));
+                *resp.status_mut() = StatusCode::FORBIDDEN;
+                return Ok(Err(resp));
+            }
+
+            Ok(Ok((user, Some(user_id))))
+        }
+    }
+}
+
+async fn latest_passport(
+    db: &DatabaseConnection,
+    user: &user::Model,
+) -> Result<Option<passport::Model>, Error> {
+    Ok(Passport::find()
+        .filter(passport::Column::OwnerId.eq(user.id))
+        .order_by_desc(passport::Column::Id)
+        .one(db)
+        .await?)
+}
+
+/// The Discord ID the issuing office is asking about, from the query string
+fn query_discord_id(req: &Request) -> Result<Option<i64>, Error> {
+    parse_discord_id(
+        url::Url::parse(&req.uri().to_string())?
+            .query_pairs()
+            .find_map(|(k, v)| if k == "discord_id" { Some(v) } else { None })
+            .as_deref(),
+    )
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    match *req.method() {
+        Method::GET => get_handler(req).await,
+        Method::POST => post_handler(req).await,
+        Method::DELETE => delete_handler(req).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+/// Shows the caller's latest passport, which ceremony it's registered for and where it
+/// is on a waitlist
+pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
+    let discord_id = query_discord_id(&req)?;
+    let audit = audit::Event::new(&req, "passport.view");
+    let Some(registrar) = registrar(req, &audit).await? else {
+        return Ok(unauthorized());
+    };
+
+    let db = db().await?;
+    let (user, _) = match registrant(&db, &registrar, discord_id, false).await? {
+        Ok(u) => u,
+        Err(resp) => return Ok(resp),
+    };
+
+    let Some(passport) = latest_passport(&db, &user).await? else {
+        let mut resp = Response::new(Body::Text("Passport does not exist".to_string()));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    };
+
+    let waitlist = match ceremony::waitlist_entry(&db, passport.id).await? {
+        Some(entry) => Some(json!({
+            "ceremony_id": entry.ceremony_id,
+            "position": ceremony::waitlist_position(&db, &entry).await?,
+        })),
+        None => None,
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "id": passport.id,
+                "status": passport.status,
+                "ceremony_id": passport.ceremony_id,
+                "waitlist": waitlist,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
+
+/// Withdraws the caller's passport from its ceremony and any waitlist, handing the seat
+/// to whoever is next in line
+pub async fn delete_handler(req: Request) -> Result<Response<Body>, Error> {
+    let discord_id = query_discord_id(&req)?;
+    let audit = audit::Event::new(&req, "passport.withdraw");
+    let Some(registrar) = registrar(req, &audit).await? else {
+        return Ok(unauthorized());
+    };
+
+    let db = db().await?;
+    let (user, actor) = match registrant(&db, &registrar, discord_id, false).await? {
+        Ok(u) => u,
+        Err(resp) => return Ok(resp),
+    };
+
+    let Some(passport) = latest_passport(&db, &user)
+        .await?
+        .filter(|p| lifecycle::is_editable(&p.status))
+    else {
+        let mut resp = Response::new(Body::Text("No passport waiting on a ceremony".to_string()));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    };
+
+    let previous = passport.ceremony_id;
+
+    let txn = db.begin().await?;
+    ceremony::leave_waitlist(&txn, passport.id).await?;
+    let passport = if passport.status == PassportStatusEnum::PendingCeremony {
+        lifecycle::transition(
+            &txn,
+            passport,
+            PassportStatusEnum::Draft,
+            Some("withdrawn from ceremony".to_string()),
+            actor,
+        )
+        .await?
+    } else {
+        passport
+    };
+    let mut am = passport.into_active_model();
+    am.ceremony_id = ActiveValue::Set(None);
+    let passport = am.update(&txn).await?;
+    txn.commit().await?;
+
+    registrar
+        .audit(audit)
+        .subject("passport", passport.id)
+        .detail(json!({ "ceremony_id": previous }))
+        .success(&db)
+        .await;
+
+    let promoted = match previous {
+        Some(previous) => ceremony::promote(&db, previous).await?.len(),
+        None => 0,
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "id": passport.id,
+                "status": passport.status,
+                "promoted": promoted,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
+
+/// Registers the caller's passport for a ceremony, or puts it on the waitlist if the
+/// ceremony is full and `waitlist` was asked for
+pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
+    let b = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => b.to_vec(),
+    };
+    let audit = audit::Event::new(&req, "passport.register");
+
+    let Some(registrar) = registrar(req, &audit).await? else {
+        return Ok(unauthorized());
+    };
+
+    let t = String::from_utf8(b)
+        .map_err(|e| format!("Bad UTF-8 encoding! Couldn't convert to text: {e}"))?;
+    let new: NewPassport = serde_json::from_str(&t)
+        .map_err(|e| format!("Bad JSON encoding! Couldn't convert to passport data: [{e}]: {t}"))?;
+    let discord_id = parse_discord_id(new.discord_id.as_deref())?;
+
+    let db = db().await?;
+
+    let (user, actor) = match registrant(&db, &registrar, discord_id, true).await? {
+        Ok(u) => u,
+        Err(resp) => return Ok(resp),
+    };
+
+    let ceremony_id =
+        match ceremony::resolve(&db, new.ceremony_id, new.ceremony_time.as_deref()).await? {
+            Ok(id) => id,
+            Err(unavailable) => return Ok(unavailable.response()),
+        };
+    let editable = latest_passport(&db, &user)
+        .await?
+        .filter(|p| lifecycle::is_editable(&p.status));
+
+    // The check locks the ceremony until the passport is saved, so two people can't both
+    // take its last seat
+    let txn = db.begin().await?;
+    match ceremony::check(&txn, ceremony_id, user.id).await? {
+        Ok(_) => {}
+        Err(ceremony::Unavailable::Full) if new.waitlist => {
+            // Keep whatever seat they already have until one opens up here
+            let passport = match editable {
                 Some(found_passport) => {
-                    if found_passport.activated {
-                        let new_passport = create_new_passport(&db, &user, new).await?;
-                        new_passport.id
-                    } else {
-                        let mut active_passport = found_passport.into_active_model();
-
-                        active_passport.name = ActiveValue::Set(new.name);
-                        active_passport.surname = ActiveValue::Set(new.surname);
-                        active_passport.date_of_birth = ActiveValue::Set(parse_date(&new.date_of_birth)?);
-                        active_passport.date_of_issue = ActiveValue::Set(parse_date(&new.date_of_issue)?);
-                        active_passport.place_of_origin = ActiveValue::Set(new.place_of_origin);
-                        active_passport.ceremony_time = ActiveValue::Set(parse_datetime(&new.ceremony_time)?);
-
-                        let updated_passport = active_passport.update(&db).await?;
-
-                        updated_passport.id
-                    }
-                }
-                None => {
-                    let new_passport = create_new_passport(&db, &user, new).await?;
-                    new_passport.id
+                    let mut active_passport = found_passport.into_active_model();
+                    update_data(&mut active_passport, new)?;
+                    active_passport.update(&txn).await?
                 }
+                None => create_new_passport(&txn, &user, new, None, actor).await?,
             };
 
-            Ok(Response::builder()
+            let entry = ceremony::join_waitlist(&txn, ceremony_id, passport.id).await?;
+            let position = ceremony::waitlist_position(&txn, &entry).await?;
+            txn.commit().await?;
+            registrar
+                .audit(audit)
+                .subject("passport", passport.id)
+                .detail(json!({ "ceremony_id": ceremony_id, "waitlist_position": position }))
+                .success(&db)
+                .await;
+
+            return Ok(Response::builder()
+                .status(StatusCode::ACCEPTED)
                 .header("Content-Type", "application/json")
                 .body(
                     json!({
-                      "id": passport_id
+                        "id": passport.id,
+                        "waitlist": {
+                            "ceremony_id": entry.ceremony_id,
+                            "position": position,
+                        },
                     })
                     .to_string()
                     .into(),
-                )?)
+                )?);
         }
+        Err(unavailable) => return Ok(unavailable.response()),
     }
+
+    let (passport_id, previous) = match editable {
+        Some(found_passport) => {
+            let previous = found_passport.ceremony_id;
+            let found_passport = if found_passport.status == PassportStatusEnum::Draft {
+                lifecycle::transition(
+                    &txn,
+                    found_passport,
+                    PassportStatusEnum::PendingCeremony,
+                    Some("registered for ceremony".to_string()),
+                    actor,
+                )
+                .await?
+            } else {
+                found_passport
+            };
+
+            let mut active_passport = found_passport.into_active_model();
+            update_data(&mut active_passport, new)?;
+            active_passport.ceremony_id = ActiveValue::Set(Some(ceremony_id));
+
+            let updated_passport = active_passport.update(&txn).await?;
+            (updated_passport.id, previous)
+        }
+        None => {
+            let new_passport =
+                create_new_passport(&txn, &user, new, Some(ceremony_id), actor).await?;
+            (new_passport.id, None)
+        }
+    };
+
+    ceremony::leave_waitlist(&txn, passport_id).await?;
+    txn.commit().await?;
+    ceremony::release(ceremony_id, user.id).await?;
+    registrar
+        .audit(audit)
+        .subject("passport", passport_id)
+        .detail(json!({ "ceremony_id": ceremony_id }))
+        .success(&db)
+        .await;
+
+    // Moving to another ceremony frees up a seat at the old one
+    if let Some(previous) = previous.filter(|p| *p != ceremony_id) {
+        ceremony::promote(&db, previous).await?;
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+              "id": passport_id
+            })
+            .to_string()
+            .into(),
+        )?)
 }
@@ -1,22 +1,137 @@
-use id::{wrap_error, oauth_user, db};
+use std::collections::HashMap;
+
+use entity::{passport, prelude::*, sea_orm_active_enums::PassportStatusEnum};
+use id::{audit, db, param, wrap_error, PassportSummary};
+use sea_orm::{prelude::*, Condition, Order, QueryOrder, QuerySelect};
+use serde_json::json;
 use vercel_runtime::{run, Body, Error, Request, Response};
-use entity::{
-    passport,
-    prelude::*,
-};
-use sea_orm::prelude::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     run(wrap_error!(handler)).await
 }
 
+const DEFAULT_LIMIT: u64 = 50;
+const MAX_LIMIT: u64 = 200;
+
+/// The column a listing is ordered by, always broken by id so the order is total
+fn sort_column(sort: &str) -> Result<(passport::Column, Order), Error> {
+    let (name, order) = match sort.strip_prefix('-') {
+        Some(name) => (name, Order::Desc),
+        None => (sort, Order::Asc),
+    };
+    let column = match name {
+        "id" => passport::Column::Id,
+        "date_of_issue" => passport::Column::DateOfIssue,
+        "status_changed_at" => passport::Column::StatusChangedAt,
+        _ => return Err(format!("Can't sort by {name}").into()),
+    };
+
+    Ok((column, order))
+}
+
+/// The sort column's value for a passport, to carry on from it
+fn sort_value(column: passport::Column, passport: &passport::Model) -> Value {
+    match column {
+        passport::Column::DateOfIssue => passport.date_of_issue.into(),
+        passport::Column::StatusChangedAt => passport.status_changed_at.into(),
+        _ => passport.id.into(),
+    }
+}
+
+/// Lists passports a page at a time, without their secrets.
+///
+/// Filters are `owner_id`, `status` (comma separated), `ceremony_id` and `version`. `sort` is
+/// `id`, `date_of_issue` or `status_changed_at`, prefixed with `-` for descending. Pass the
+/// `next_cursor` from one page as `cursor` to get the next.
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let _user = oauth_user(req, vec!["admin:read".parse().expect("scope to parse")]).await?;
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+
+    let audit = audit::Event::new(&req, "passport.list").detail(json!(params));
+    let admin = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let mut query = Passport::find();
+    if let Some(owner_id) = param::<i32>(&params, "owner_id")? {
+        query = query.filter(passport::Column::OwnerId.eq(owner_id));
+    }
+    if let Some(statuses) = params.get("status") {
+        let statuses: Vec<PassportStatusEnum> = statuses
+            .split(',')
+            .map(|s| serde_json::from_value(serde_json::Value::String(s.to_string())))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Unknown status! {e}"))?;
+        query = query.filter(passport::Column::Status.is_in(statuses));
+    }
+    if let Some(ceremony_id) = param::<i32>(&params, "ceremony_id")? {
+        query = query.filter(passport::Column::CeremonyId.eq(ceremony_id));
+    }
+    if let Some(version) = param::<i32>(&params, "version")? {
+        query = query.filter(passport::Column::Version.eq(version));
+    }
+
+    let (column, order) = sort_column(params.get("sort").map_or("id", String::as_str))?;
+    let limit = param::<u64>(&params, "limit")?
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT);
 
     let db = db().await?;
+    audit.actor(admin).success(&db).await;
+
+    // The cursor is the last passport of the previous page
+    if let Some(cursor) = param::<i32>(&params, "cursor")? {
+        let last = Passport::find_by_id(cursor)
+            .one(&db)
+            .await?
+            .ok_or("Invalid cursor".to_string())?;
+        let value = sort_value(column, &last);
+
+        query = query.filter(match order {
+            Order::Desc => Condition::any()
+                .add(Expr::col(column).lt(value.clone()))
+                .add(
+                    Condition::all()
+                        .add(Expr::col(column).eq(value))
+                        .add(passport::Column::Id.lt(last.id)),
+                ),
+            _ => Condition::any()
+                .add(Expr::col(column).gt(value.clone()))
+                .add(
+                    Condition::all()
+                        .add(Expr::col(column).eq(value))
+                        .add(passport::Column::Id.gt(last.id)),
+                ),
+        });
+    }
+
+    let mut page: Vec<passport::Model> = query
+        .order_by(column, order.clone())
+        .order_by(passport::Column::Id, order)
+        .limit(limit + 1)
+        .all(&db)
+        .await?;
+
+    let next_cursor = if page.len() as u64 > limit {
+        page.truncate(limit as usize);
+        page.last().map(|p| p.id.to_string())
+    } else {
+        None
+    };
 
-    let all_passports: Vec<passport::Model> = Passport::find().all(&db).await?;
+    let passports: Vec<PassportSummary> = page.into_iter().map(PassportSummary::from).collect();
 
-    Ok(Response::new(Body::Text(serde_json::to_string(&all_passports).expect("convert passports to string array"))))
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "passports": passports,
+                "next_cursor": next_cursor,
+            })
+            .to_string()
+            .into(),
+        )?)
 }
@@ -1,19 +1,58 @@
-use id::{wrap_error, oauth_user, db};
-use vercel_runtime::{run, Body, Error, Request, Response};
-use entity::{
-    passport,
-    prelude::*,
-};
-use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use entity::{passport, prelude::*, sea_orm_active_enums::PassportStatusEnum};
+use id::{audit, ceremony, db, lifecycle, wrap_error, APIError};
+use sea_orm::prelude::*;
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     run(wrap_error!(handler)).await
 }
 
+/// Requested status change. With no body the passport is activated.
+#[derive(Debug, serde::Deserialize)]
+struct StatusChange {
+    status: PassportStatusEnum,
+    reason: Option<String>,
+}
+
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let id: i32 = req.uri().path().split('/').last().expect("id path component").parse().expect("valid id");
-    let _user = oauth_user(req, vec!["admin".parse().expect("scope to parse")]).await?;
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .expect("valid id");
+
+    let change: Option<StatusChange> = match req.body() {
+        Body::Empty => None,
+        Body::Text(_) => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => Some(serde_json::from_slice(b)?),
+    };
+
+    let activating = matches!(
+        change,
+        None | Some(StatusChange {
+            status: PassportStatusEnum::Active,
+            ..
+        })
+    );
+    let audit = audit::Event::new(
+        &req,
+        if activating {
+            "passport.activate"
+        } else {
+            "passport.status"
+        },
+    )
+    .subject("passport", id);
+
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+    let audit = audit.actor(admin);
 
     let db = db().await?;
 
@@ -21,23 +60,69 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
 
     let passport = passport.ok_or("Passport does not exist".to_string())?;
 
-    let old_passports: Vec<passport::Model> = Passport::find()
-        .filter(passport::Column::OwnerId.eq(passport.owner_id))
-        .all(&db)
-        .await?
-        .into_iter()
-        .filter(|p| p.id != passport.id)
-        .collect();
-
-    let mut am = passport.into_active_model();
-    am.activated = ActiveValue::Set(true);
-    am.save(&db).await?;
-
-    for passport in old_passports {
-        let mut am = passport.into_active_model();
-        am.activated = ActiveValue::Set(false);
-        am.save(&db).await?;
+    // Passports being handed out for the first time have to have been made at their ceremony
+    if activating
+        && ceremony::attendance_required()
+        && matches!(
+            passport.status,
+            PassportStatusEnum::PendingCeremony | PassportStatusEnum::Issued
+        )
+        && ceremony::attendance(&db, &passport).await?.is_none()
+    {
+        audit
+            .detail(json!({ "code": "not_attended" }))
+            .denied(&db)
+            .await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "This passport wasn't checked in at its ceremony",
+            code: "not_attended",
+        })?));
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
     }
 
-    Ok(Response::new(Body::Empty))
+    let from = passport.status.clone();
+    let result = match change {
+        None => lifecycle::activate(&db, passport, None, Some(admin)).await,
+        Some(StatusChange {
+            status: PassportStatusEnum::Active,
+            reason,
+        }) => lifecycle::activate(&db, passport, reason, Some(admin)).await,
+        Some(StatusChange { status, reason }) => {
+            lifecycle::transition(&db, passport, status, reason, Some(admin)).await
+        }
+    };
+    let passport = match result {
+        Ok(passport) => passport,
+        Err(e) => {
+            audit
+                .detail(json!({ "from": from, "error": e.to_string() }))
+                .failure(&db)
+                .await;
+            if let Some(e @ id::Error::InvalidTransition { .. }) = e.downcast_ref() {
+                let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+                    message: &e.to_string(),
+                    code: "invalid_transition",
+                })?));
+                *resp.status_mut() = StatusCode::CONFLICT;
+                return Ok(resp);
+            }
+            return Err(e);
+        }
+    };
+    audit
+        .detail(json!({ "from": from, "to": passport.status }))
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "id": passport.id,
+                "status": passport.status,
+            })
+            .to_string()
+            .into(),
+        )?)
 }
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use chrono::{Days, Utc};
+use entity::{passport, prelude::*, sea_orm_active_enums::PassportStatusEnum};
+use id::{audit, db, lifecycle, param, wrap_error};
+use sea_orm::{prelude::*, QueryOrder};
+use vercel_runtime::{run, Body, Error, Request, Response};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ExpiringPassport {
+    id: i32,
+    owner_id: i32,
+    name: String,
+    surname: String,
+    date_of_issue: ChronoDate,
+    expires_on: ChronoDate,
+    status: PassportStatusEnum,
+}
+
+/// Lists usable passports that run out within `days` days (60 by default), soonest first
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+    let days = param::<u64>(&params, "days")?.unwrap_or(60);
+
+    let audit = audit::Event::new(&req, "passport.expiring");
+    let admin = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let horizon = Utc::now()
+        .date_naive()
+        .checked_add_days(Days::new(days))
+        .ok_or("Horizon out of range".to_string())?;
+    let issued_before = horizon
+        .checked_sub_months(lifecycle::validity())
+        .ok_or("Horizon out of range".to_string())?;
+
+    let db = db().await?;
+    audit.actor(admin).success(&db).await;
+
+    let expiring: Vec<ExpiringPassport> = Passport::find()
+        .filter(
+            passport::Column::Status
+                .is_in([PassportStatusEnum::Active, PassportStatusEnum::Suspended]),
+        )
+        .filter(passport::Column::DateOfIssue.lte(issued_before))
+        .order_by_asc(passport::Column::DateOfIssue)
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|p| ExpiringPassport {
+            expires_on: lifecycle::expires_on(&p),
+            id: p.id,
+            owner_id: p.owner_id,
+            name: p.name,
+            surname: p.surname,
+            date_of_issue: p.date_of_issue,
+            status: p.status,
+        })
+        .collect();
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&expiring)?.into())?)
+}
@@ -1,8 +1,8 @@
 use std::str::FromStr;
 
-use entity::{passport, prelude::*, sea_orm_active_enums::RoleEnum, user};
+use entity::{passport, prelude::*, user};
 use fred::prelude::*;
-use id::{db, kv, wrap_error, PassportRecord};
+use id::{audit, db, kv, lifecycle, roles, tfa, webauthn, wrap_error, PassportRecord};
 use lambda_http::http::Method;
 use sea_orm::prelude::*;
 use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
@@ -57,11 +57,20 @@ pub async fn get_handler(req: Request) -> Result<Response<Body>, Error> {
         #[derive(Debug, serde::Serialize)]
         struct GetReturn {
             totp_needed: bool,
+            /// The owner can answer with a passkey instead, through `/api/webauthn`
+            webauthn_available: bool,
+            /// The owner's role needs a second factor but they haven't set one up yet
+            totp_enrolment_needed: bool,
         }
 
+        let webauthn_available = webauthn::has_credentials(&db, user.id).await?;
+
         Ok(Response::new(Body::Text(serde_json::to_string(
             &GetReturn {
-                totp_needed: user.role == RoleEnum::Admin,
+                totp_needed: user.totp.is_some(),
+                webauthn_available,
+                totp_enrolment_needed: roles::requires_totp(&user.role)
+                    && !tfa::enrolled(&db, &user).await?,
             },
         )?)))
     } else {
@@ -78,6 +87,7 @@ pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
         Body::Binary(b) => {
             let t = String::from_utf8(b.to_vec())?;
             let record: PassportRecord = serde_json::from_str(&t)?;
+            let audit = audit::Event::new(&req, "passport.scan").subject("passport", record.id);
 
             let db = db().await?;
             let kv = kv().await?;
@@ -89,16 +99,19 @@ pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
                 .one(&db)
                 .await?
                 .ok_or("Invalid passport ID".to_string())?;
+            let passport = lifecycle::expire_if_due(&db, passport).await?;
+            let audit = audit.actor(passport.owner_id);
 
-            if !passport.activated {
-                let mut resp = Response::new(Body::Text("Passport disabled".to_string()));
+            if let Some(refusal) = lifecycle::refusal(&passport.status) {
+                audit.denied(&db).await;
+                let mut resp = Response::new(Body::Text(refusal.to_string()));
                 *resp.status_mut() = StatusCode::FORBIDDEN;
                 return Ok(resp);
             }
 
             // No record currently, so add a record with whatever the secret is supposed to be
             if !kv.exists(passport.id).await? {
-                kv.set(passport.id, false, Some(Expiration::EX(90)), None, false)
+                kv.set::<(), _, _>(passport.id, false, Some(Expiration::EX(90)), None, false)
                     .await?;
                 return Ok(Response::new(Body::Empty));
             }
@@ -109,11 +122,13 @@ pub async fn post_handler(req: Request) -> Result<Response<Body>, Error> {
             // If it's not or there is already a valid secret in the KV, return error
             let current_value: bool = kv.get(passport.id).await?;
             if !current_value && record.secret == passport.secret {
-                kv.set(passport.id, true, Some(Expiration::EX(60)), None, false)
+                kv.set::<(), _, _>(passport.id, true, Some(Expiration::EX(60)), None, false)
                     .await?;
+                audit.success(&db).await;
 
                 Ok(Response::new(Body::Empty))
             } else {
+                audit.failure(&db).await;
                 let mut resp = Response::new(Body::Text("Invalid KV request".to_string()));
                 *resp.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(resp)
@@ -1,7 +1,8 @@
-use id::{wrap_error, OAuthEndpoint, RequestCompat, ResponseCompat};
+use id::{audit, db, wrap_error, OAuthEndpoint, RequestCompat, ResponseCompat};
 use oxide_auth::endpoint::{OwnerConsent, Solicitation};
 use oxide_auth_async::endpoint::access_token::AccessTokenFlow;
 use oxide_auth_async::endpoint::OwnerSolicitor;
+use serde_json::json;
 use vercel_runtime::{run, Body, Error, Request, Response};
 
 #[tokio::main]
@@ -58,7 +59,14 @@ impl OwnerSolicitor<RequestCompat> for TokenSolicitor {
 }
 
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    Ok(AccessTokenFlow::prepare(OAuthEndpoint::new(
+    let mut audit = audit::Event::new(&req, "token.issue");
+    if let Body::Binary(b) = req.body() {
+        if let Some((_, client_id)) = form_urlencoded::parse(b).find(|(k, _)| k == "client_id") {
+            audit = audit.client(client_id);
+        }
+    }
+
+    let res = AccessTokenFlow::prepare(OAuthEndpoint::new(
         TokenSolicitor,
         vec!["user".parse().expect("scope to parse")],
     ))
@@ -66,5 +74,17 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
     .execute(RequestCompat(req))
     .await
     .map_err(|e| format!("Access token flow exec error: {e}"))?
-    .0)
+    .0;
+
+    let db = db().await?;
+    if res.status().is_success() {
+        audit.success(&db).await;
+    } else {
+        audit
+            .detail(json!({ "status": res.status().as_u16() }))
+            .failure(&db)
+            .await;
+    }
+
+    Ok(res)
 }
@@ -1,18 +1,32 @@
 use entity::{passport, prelude::*, sea_orm_active_enums::RoleEnum, user};
-use id::{db, oauth_user, wrap_error};
+use id::{
+    audit, db, groups, wrap_error, PassportSummary, DISCORD_SCOPE, PASSPORT_SCOPE, PROFILE_SCOPE,
+};
 use sea_orm::{prelude::*, QueryOrder};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use vercel_runtime::{run, Body, Error, Request, Response};
 
-#[derive(Serialize, Deserialize)]
-struct UserWithPassport {
+/// Claims about a user, each group only present when the grant's scope allows it
+#[derive(Serialize)]
+struct UserClaims {
     iss: String,
     sub: i32,
     id: i32,
-    discord_id: i64,
-    role: RoleEnum,
-    totp: Option<String>,
-    latest_passport: Option<passport::Model>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<RoleEnum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    totp_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discord_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discord_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    discord_avatar: Option<String>,
+    /// Present (possibly null) with the passport scope
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_passport: Option<Option<PassportSummary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<String>>,
 }
 
 #[tokio::main]
@@ -21,7 +35,11 @@ async fn main() -> Result<(), Error> {
 }
 
 pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
-    let user_id = oauth_user(req, vec!["user:read".parse().expect("valid scope")]).await?;
+    let audit = audit::Event::new(&req, "user.info");
+    let (user_id, scope) = audit
+        .oauth_grant(req, vec!["user:read".parse().expect("valid scope")])
+        .await?;
+    let granted = |s: &str| scope.iter().any(|g| g == s);
 
     let db = db().await?;
 
@@ -30,23 +48,47 @@ pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
         .one(&db)
         .await?
         .ok_or_else(|| Error::from("User not found"))?;
-    let latest_passport = Passport::find()
-        .filter(passport::Column::OwnerId.eq(user_id))
-        .order_by_desc(passport::Column::Id)
-        .one(&db)
-        .await?;
 
-    let response_data = UserWithPassport {
+    let mut claims = UserClaims {
         iss: "https://id.purduehackers.com".to_owned(),
         sub: user.id,
         id: user.id,
-        discord_id: user.discord_id.clone(),
-        role: user.role.clone(),
-        totp: user.totp.clone(),
-        latest_passport: latest_passport.clone(),
+        role: None,
+        totp_enabled: None,
+        discord_id: None,
+        discord_username: None,
+        discord_avatar: None,
+        latest_passport: None,
+        groups: None,
     };
 
-    Ok(Response::new(Body::Text(serde_json::to_string(
-        &response_data,
-    )?)))
+    if granted(PROFILE_SCOPE) {
+        claims.role = Some(user.role);
+        claims.totp_enabled = Some(user.totp.is_some());
+    }
+    if granted(DISCORD_SCOPE) {
+        claims.discord_id = Some(user.discord_id);
+        claims.discord_username = user.discord_username;
+        claims.discord_avatar = user.discord_avatar;
+    }
+    if granted(PASSPORT_SCOPE) {
+        let latest_passport = Passport::find()
+            .filter(passport::Column::OwnerId.eq(user_id))
+            .order_by_desc(passport::Column::Id)
+            .one(&db)
+            .await?;
+        claims.latest_passport = Some(latest_passport.map(PassportSummary::from));
+    }
+    if granted(groups::SCOPE) {
+        claims.groups = Some(groups::slugs(&db, user_id).await?);
+    }
+
+    audit
+        .actor(user_id)
+        .subject("user", user_id)
+        .detail(serde_json::json!({ "scope": scope.to_string() }))
+        .success(&db)
+        .await;
+
+    Ok(Response::new(Body::Text(serde_json::to_string(&claims)?)))
 }
@@ -0,0 +1,227 @@
+use chrono::Utc;
+use entity::{
+    auth_grant, auth_session, passport, prelude::*, sea_orm_active_enums::RoleEnum, user,
+};
+use id::{audit, db, notify_admins, roles, tfa, wrap_error, APIError, PassportSummary};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, QueryOrder, TransactionTrait};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Changes an admin can make to an account
+#[derive(Debug, serde::Deserialize)]
+struct UserEdit {
+    role: Option<RoleEnum>,
+    /// Clears the TOTP seed so the user can enrol again
+    #[serde(default)]
+    reset_totp: bool,
+    disabled: Option<bool>,
+    reason: Option<String>,
+}
+
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let id: i32 = req
+        .uri()
+        .path()
+        .split('/')
+        .next_back()
+        .expect("id path component")
+        .parse()
+        .map_err(|e| format!("Invalid user ID! {e}"))?;
+
+    match *req.method() {
+        Method::GET => get_handler(req, id).await,
+        Method::PATCH => patch_handler(req, id).await,
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
+
+async fn find(db: &DatabaseConnection, id: i32) -> Result<Option<user::Model>, Error> {
+    Ok(User::find_by_id(id).one(db).await?)
+}
+
+fn not_found() -> Response<Body> {
+    let mut resp = Response::new(Body::Text("User does not exist".to_string()));
+    *resp.status_mut() = StatusCode::NOT_FOUND;
+    resp
+}
+
+fn user_json(user: &user::Model) -> serde_json::Value {
+    json!({
+        "id": user.id,
+        "discord_id": user.discord_id,
+        "discord_username": user.discord_username,
+        "discord_avatar": user.discord_avatar,
+        "discord_verified_at": user.discord_verified_at,
+        "role": user.role,
+        "totp_enabled": user.totp.is_some(),
+        "disabled_at": user.disabled_at,
+        "disabled_reason": user.disabled_reason,
+    })
+}
+
+/// Shows a user along with their passports and open sessions
+pub async fn get_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let audit = audit::Event::new(&req, "user.view").subject("user", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let db = db().await?;
+    let Some(user) = find(&db, id).await? else {
+        return Ok(not_found());
+    };
+
+    let passports: Vec<PassportSummary> = Passport::find()
+        .filter(passport::Column::OwnerId.eq(user.id))
+        .order_by_desc(passport::Column::Id)
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(PassportSummary::from)
+        .collect();
+
+    // Session tokens are as good as a password, so only their metadata is shown
+    let sessions: Vec<serde_json::Value> = AuthSession::find()
+        .filter(auth_session::Column::OwnerId.eq(user.id))
+        .filter(auth_session::Column::Until.gte(Utc::now()))
+        .order_by_desc(auth_session::Column::Until)
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|s| {
+            json!({
+                "id": s.id,
+                "until": s.until,
+                "passport_id": s.passport_id,
+                "auth_time": s.auth_time,
+                "amr": s.amr,
+            })
+        })
+        .collect();
+    audit.actor(admin).success(&db).await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "user": user_json(&user),
+                "passports": passports,
+                "sessions": sessions,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
+
+/// Changes a user's role, resets their TOTP or disables their account
+pub async fn patch_handler(req: Request, id: i32) -> Result<Response<Body>, Error> {
+    let edit: UserEdit = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "user.update").subject("user", id);
+    let admin = audit
+        .oauth_user(req, vec!["admin".parse().expect("scope to parse")])
+        .await?;
+    let audit = audit.actor(admin).detail(json!({
+        "role": edit.role,
+        "reset_totp": edit.reset_totp,
+        "disabled": edit.disabled,
+        "reason": edit.reason,
+    }));
+
+    // Locking yourself out has to be done by someone else
+    if admin == id && (edit.role.is_some() || edit.disabled == Some(true)) {
+        let db = db().await?;
+        audit.denied(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "You can't change your own role or disable yourself",
+            code: "self_modification",
+        })?));
+        *resp.status_mut() = StatusCode::CONFLICT;
+        return Ok(resp);
+    }
+
+    let db = db().await?;
+    let Some(user) = find(&db, id).await? else {
+        return Ok(not_found());
+    };
+
+    let txn = db.begin().await?;
+
+    let mut am = user.clone().into_active_model();
+    if let Some(role) = edit.role.clone() {
+        am.role = ActiveValue::Set(role);
+    }
+    if edit.reset_totp {
+        am.totp = ActiveValue::Set(None);
+        am.totp_last_step = ActiveValue::Set(None);
+        tfa::clear_recovery_codes(&txn, user.id).await?;
+    }
+    let disabling = edit.disabled == Some(true) && user.disabled_at.is_none();
+    let demoting = edit
+        .role
+        .as_ref()
+        .is_some_and(|role| roles::is_downgrade(&user.role, role));
+    match edit.disabled {
+        Some(true) if disabling => {
+            am.disabled_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+            am.disabled_reason = ActiveValue::Set(edit.reason.clone());
+        }
+        Some(false) => {
+            am.disabled_at = ActiveValue::Set(None);
+            am.disabled_reason = ActiveValue::Set(None);
+        }
+        _ => {}
+    }
+    // Tokens carry the scopes they were granted with, so they have to go along with
+    // whatever the user is no longer allowed. Deleting the grants also invalidates every
+    // token issued from them.
+    let mut revoked = None;
+    if disabling || demoting {
+        let sessions = AuthSession::delete_many()
+            .filter(auth_session::Column::OwnerId.eq(user.id))
+            .exec(&txn)
+            .await?;
+        let grants = AuthGrant::delete_many()
+            .filter(auth_grant::Column::OwnerId.eq(user.id))
+            .exec(&txn)
+            .await?;
+        revoked = Some((sessions.rows_affected, grants.rows_affected));
+    }
+    let updated = am.update(&txn).await?;
+
+    txn.commit().await?;
+    audit.success(&db).await;
+
+    match revoked {
+        Some((sessions, grants)) if disabling => {
+            notify_admins(&format!(
+                "User {id} was disabled by admin {admin}. Revoked {sessions} sessions and {grants} grants."
+            ))
+            .await
+        }
+        Some((sessions, grants)) => {
+            notify_admins(&format!(
+                "User {id} was moved from {:?} to {:?} by admin {admin}. Revoked {sessions} sessions and {grants} grants.",
+                user.role, updated.role
+            ))
+            .await
+        }
+        None if edit.disabled == Some(false) && user.disabled_at.is_some() => {
+            notify_admins(&format!("User {id} was re-enabled by admin {admin}.")).await
+        }
+        None => {}
+    }
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(user_json(&updated).to_string().into())?)
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use entity::{door_access, prelude::*};
+use id::{audit, db, door, session, wrap_error};
+use lambda_http::http::Method;
+use sea_orm::prelude::*;
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// Shows a logged in user their own door taps, newest first. Takes the same `since`, `until`
+/// and `cursor` parameters as the admin door log.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    if req.method() != Method::GET {
+        return Err("Invalid method".to_string().into());
+    }
+
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+
+    let audit = audit::Event::new(&req, "door.history").detail(json!(params));
+    let db = db().await?;
+
+    let Some(session) = session(&db, &req).await? else {
+        audit.denied(&db).await;
+        let mut resp = Response::new(Body::Text("Not logged in".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    };
+
+    let query = DoorAccess::find().filter(door_access::Column::OwnerId.eq(session.owner_id));
+    let (query, limit) = door::window(query, &params)?;
+    let (entries, next_cursor) = door::page(&db, query, limit).await?;
+    audit.actor(session.owner_id).success(&db).await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "entries": entries,
+                "next_cursor": next_cursor,
+            })
+            .to_string()
+            .into(),
+        )?)
+}
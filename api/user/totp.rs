@@ -0,0 +1,209 @@
+use entity::{prelude::*, user};
+use id::{audit, db, roles, session, tfa, webauthn, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, TransactionTrait};
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Code {
+    code: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Confirmation {
+    code: String,
+    #[serde(flatten)]
+    proof: tfa::Proof,
+}
+
+fn error(status: StatusCode, message: &str, code: &str) -> Result<Response<Body>, Error> {
+    let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+        message,
+        code,
+    })?));
+    *resp.status_mut() = status;
+    Ok(resp)
+}
+
+fn code(req: &Request) -> Result<String, Error> {
+    match req.body() {
+        Body::Text(_) | Body::Empty => Err("Invalid body".to_string().into()),
+        Body::Binary(b) => Ok(serde_json::from_slice::<Code>(b)?.code),
+    }
+}
+
+/// Lets a logged in user set up or remove TOTP.
+///
+/// POST starts an enrolment with a fresh secret, which PUT confirms with the first code from
+/// it and answers with recovery codes. Someone who already has a passkey confirms with a
+/// `webauthn` assertion as well. PATCH swaps the recovery codes for new ones and DELETE
+/// removes TOTP, both after checking a current code.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let db = db().await?;
+
+    let Some(session) = session(&db, &req).await? else {
+        let mut resp = Response::new(Body::Text("Not logged in".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    };
+
+    let user: user::Model = User::find_by_id(session.owner_id)
+        .one(&db)
+        .await?
+        .ok_or("User not found".to_string())?;
+    let user_id = user.id;
+    let event = |action| {
+        audit::Event::new(&req, action)
+            .actor(user_id)
+            .subject("user", user_id)
+    };
+
+    match *req.method() {
+        Method::GET => Ok(Response::builder()
+            .header("Content-Type", "application/json")
+            .body(
+                json!({
+                    "enabled": user.totp.is_some(),
+                    "required": roles::requires_totp(&user.role),
+                })
+                .to_string()
+                .into(),
+            )?),
+        Method::POST => {
+            if user.totp.is_some() {
+                return error(
+                    StatusCode::CONFLICT,
+                    "TOTP is already set up",
+                    "totp_enabled",
+                );
+            }
+
+            let enrolment = tfa::start_enrolment(user.id).await?;
+            event("totp.enrol_start").success(&db).await;
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&enrolment)?.into())?)
+        }
+        Method::PUT => {
+            if user.totp.is_some() {
+                return error(
+                    StatusCode::CONFLICT,
+                    "TOTP is already set up",
+                    "totp_enabled",
+                );
+            }
+
+            let audit = event("totp.enrol");
+            let confirmation: Confirmation = match req.body() {
+                Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+                Body::Binary(b) => serde_json::from_slice(b)?,
+            };
+            if !tfa::proven(&db, &user, &confirmation.proof).await? {
+                audit.denied(&db).await;
+                return error(
+                    StatusCode::UNAUTHORIZED,
+                    "Confirm with one of your passkeys first",
+                    "second_factor_required",
+                );
+            }
+
+            let Some((sealed, step)) = tfa::confirm_enrolment(user.id, &confirmation.code).await?
+            else {
+                audit.failure(&db).await;
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "That code doesn't match a pending enrolment",
+                    "invalid_code",
+                );
+            };
+
+            let txn = db.begin().await?;
+            let mut am = user.into_active_model();
+            am.totp = ActiveValue::Set(Some(sealed));
+            am.totp_last_step = ActiveValue::Set(Some(step));
+            let user = am.update(&txn).await?;
+            let recovery_codes = tfa::generate_recovery_codes(&txn, user.id).await?;
+            txn.commit().await?;
+            audit.success(&db).await;
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(
+                    json!({ "recovery_codes": recovery_codes })
+                        .to_string()
+                        .into(),
+                )?)
+        }
+        Method::PATCH => {
+            if user.totp.is_none() {
+                return error(StatusCode::NOT_FOUND, "TOTP is not set up", "totp_disabled");
+            }
+
+            let audit = event("totp.recovery_codes");
+            let code = code(&req)?;
+            if !tfa::verify(&db, &user, &code).await? {
+                audit.failure(&db).await;
+                return error(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid TOTP code",
+                    "invalid_code",
+                );
+            }
+
+            let recovery_codes = tfa::generate_recovery_codes(&db, user.id).await?;
+            audit.success(&db).await;
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(
+                    json!({ "recovery_codes": recovery_codes })
+                        .to_string()
+                        .into(),
+                )?)
+        }
+        Method::DELETE => {
+            if user.totp.is_none() {
+                return error(StatusCode::NOT_FOUND, "TOTP is not set up", "totp_disabled");
+            }
+
+            let audit = event("totp.remove");
+            if roles::requires_totp(&user.role) && !webauthn::has_credentials(&db, user.id).await? {
+                audit.denied(&db).await;
+                return error(
+                    StatusCode::FORBIDDEN,
+                    "Your role has to keep TOTP or a passkey set up",
+                    "totp_required",
+                );
+            }
+
+            let code = code(&req)?;
+            if !tfa::verify(&db, &user, &code).await? {
+                audit.failure(&db).await;
+                return error(
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid TOTP code",
+                    "invalid_code",
+                );
+            }
+
+            let txn = db.begin().await?;
+            let mut am = user.into_active_model();
+            am.totp = ActiveValue::Set(None);
+            am.totp_last_step = ActiveValue::Set(None);
+            let user = am.update(&txn).await?;
+            tfa::clear_recovery_codes(&txn, user.id).await?;
+            txn.commit().await?;
+            audit.success(&db).await;
+
+            Ok(Response::new(Body::Empty))
+        }
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
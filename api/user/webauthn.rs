@@ -0,0 +1,190 @@
+use entity::{prelude::*, user, webauthn_credential};
+use id::{audit, db, roles, session, tfa, webauthn, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::prelude::*;
+use serde_json::json;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+use webauthn_rs::prelude::RegisterPublicKeyCredential;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Registration {
+    name: Option<String>,
+    credential: RegisterPublicKeyCredential,
+    #[serde(flatten)]
+    proof: tfa::Proof,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Removal {
+    id: i32,
+    #[serde(flatten)]
+    proof: tfa::Proof,
+}
+
+fn error(status: StatusCode, message: &str, code: &str) -> Result<Response<Body>, Error> {
+    let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+        message,
+        code,
+    })?));
+    *resp.status_mut() = status;
+    Ok(resp)
+}
+
+fn unproven() -> Result<Response<Body>, Error> {
+    error(
+        StatusCode::UNAUTHORIZED,
+        "Confirm with TOTP or one of your passkeys first",
+        "second_factor_required",
+    )
+}
+
+/// A passkey without the key itself
+fn credential_json(c: &webauthn_credential::Model) -> serde_json::Value {
+    json!({
+        "id": c.id,
+        "name": c.name,
+        "created_at": c.created_at,
+        "last_used_at": c.last_used_at,
+    })
+}
+
+/// Lets a logged in user manage their passkeys.
+///
+/// POST starts a registration and PUT finishes it with the browser's response. GET lists
+/// passkeys and DELETE removes one by id. Once a user has a second factor, PUT and DELETE
+/// also need a `totp_code` or a `webauthn` assertion answering a challenge from PATCH.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let db = db().await?;
+
+    let Some(session) = session(&db, &req).await? else {
+        let mut resp = Response::new(Body::Text("Not logged in".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    };
+
+    let user: user::Model = User::find_by_id(session.owner_id)
+        .one(&db)
+        .await?
+        .ok_or("User not found".to_string())?;
+    let event = |action| {
+        audit::Event::new(&req, action)
+            .actor(user.id)
+            .subject("user", user.id)
+    };
+
+    match *req.method() {
+        Method::GET => {
+            let credentials: Vec<serde_json::Value> = webauthn::credentials(&db, user.id)
+                .await?
+                .iter()
+                .map(credential_json)
+                .collect();
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(json!({ "credentials": credentials }).to_string().into())?)
+        }
+        Method::POST => {
+            let challenge = webauthn::start_registration(&db, &user).await?;
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&challenge)?.into())?)
+        }
+        Method::PATCH => {
+            let Some(challenge) = webauthn::start_authentication(&db, user.id).await? else {
+                return error(
+                    StatusCode::NOT_FOUND,
+                    "No passkeys are set up",
+                    "no_passkeys",
+                );
+            };
+
+            Ok(Response::builder()
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&challenge)?.into())?)
+        }
+        Method::PUT => {
+            let registration: Registration = match req.body() {
+                Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+                Body::Binary(b) => serde_json::from_slice(b)?,
+            };
+
+            let audit = event("passkey.register");
+            if !tfa::proven(&db, &user, &registration.proof).await? {
+                audit.denied(&db).await;
+                return unproven();
+            }
+
+            let Some(credential) = webauthn::finish_registration(
+                &db,
+                user.id,
+                registration.name,
+                &registration.credential,
+            )
+            .await?
+            else {
+                audit.failure(&db).await;
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    "That doesn't answer a pending registration",
+                    "invalid_credential",
+                );
+            };
+            audit
+                .detail(json!({ "credential_id": credential.id }))
+                .success(&db)
+                .await;
+
+            let mut resp = Response::builder()
+                .header("Content-Type", "application/json")
+                .body(credential_json(&credential).to_string().into())?;
+            *resp.status_mut() = StatusCode::CREATED;
+            Ok(resp)
+        }
+        Method::DELETE => {
+            let removal: Removal = match req.body() {
+                Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+                Body::Binary(b) => serde_json::from_slice(b)?,
+            };
+
+            let audit = event("passkey.remove").detail(json!({ "credential_id": removal.id }));
+            let credentials = webauthn::credentials(&db, user.id).await?;
+            let Some(credential) = credentials.iter().find(|c| c.id == removal.id) else {
+                return error(
+                    StatusCode::NOT_FOUND,
+                    "There is no such passkey",
+                    "not_found",
+                );
+            };
+
+            // Roles that need a second factor can't remove their last one
+            if roles::requires_totp(&user.role) && user.totp.is_none() && credentials.len() == 1 {
+                audit.denied(&db).await;
+                return error(
+                    StatusCode::FORBIDDEN,
+                    "Your role has to keep TOTP or a passkey set up",
+                    "totp_required",
+                );
+            }
+
+            if !tfa::proven(&db, &user, &removal.proof).await? {
+                audit.denied(&db).await;
+                return unproven();
+            }
+
+            WebauthnCredential::delete_by_id(credential.id)
+                .exec(&db)
+                .await?;
+            audit.success(&db).await;
+
+            Ok(Response::new(Body::Empty))
+        }
+        _ => Err("Invalid method".to_string().into()),
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use entity::{prelude::*, sea_orm_active_enums::RoleEnum, user};
+use id::{audit, db, param, wrap_error};
+use sea_orm::{prelude::*, Condition, QueryOrder, QuerySelect};
+use vercel_runtime::{run, Body, Error, Request, Response};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+/// A user as shown to admins, without their TOTP seed
+#[derive(Debug, serde::Serialize)]
+struct UserSummary {
+    id: i32,
+    discord_id: i64,
+    discord_username: Option<String>,
+    role: RoleEnum,
+    totp_enabled: bool,
+    disabled_at: Option<DateTimeWithTimeZone>,
+}
+
+/// Escapes the characters `LIKE` treats specially, so they only match themselves. Postgres
+/// escapes with a backslash by default.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Searches users. `q` matches a user or Discord id exactly or part of a Discord username,
+/// and `role` and `disabled` narrow it down further.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    let params: HashMap<String, String> = url::Url::parse(&req.uri().to_string())?
+        .query_pairs()
+        .into_owned()
+        .collect();
+
+    let audit = audit::Event::new(&req, "user.list");
+    let user = audit
+        .oauth_user(req, vec!["admin:read".parse().expect("scope to parse")])
+        .await?;
+
+    let mut query = User::find();
+    if let Some(q) = params.get("q").filter(|q| !q.is_empty()) {
+        let mut matches =
+            Condition::any().add(user::Column::DiscordUsername.contains(escape_like(q)));
+        if let Ok(id) = q.parse::<i64>() {
+            matches = matches.add(user::Column::DiscordId.eq(id));
+            if let Ok(id) = i32::try_from(id) {
+                matches = matches.add(user::Column::Id.eq(id));
+            }
+        }
+        query = query.filter(matches);
+    }
+    if let Some(role) = params.get("role") {
+        let role: RoleEnum = serde_json::from_value(serde_json::Value::String(role.clone()))
+            .map_err(|e| format!("Unknown role! {e}"))?;
+        query = query.filter(user::Column::Role.eq(role));
+    }
+    if let Some(disabled) = param::<bool>(&params, "disabled")? {
+        query = query.filter(if disabled {
+            user::Column::DisabledAt.is_not_null()
+        } else {
+            user::Column::DisabledAt.is_null()
+        });
+    }
+    let limit = param::<u64>(&params, "limit")?.unwrap_or(50);
+
+    let db = db().await?;
+
+    let users: Vec<UserSummary> = query
+        .order_by_asc(user::Column::Id)
+        .limit(limit.min(200))
+        .all(&db)
+        .await?
+        .into_iter()
+        .map(|u| UserSummary {
+            id: u.id,
+            discord_id: u.discord_id,
+            discord_username: u.discord_username,
+            role: u.role,
+            totp_enabled: u.totp.is_some(),
+            disabled_at: u.disabled_at,
+        })
+        .collect();
+    audit
+        .actor(user)
+        .detail(serde_json::to_value(&params)?)
+        .success(&db)
+        .await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&users)?.into())?)
+}
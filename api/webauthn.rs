@@ -0,0 +1,64 @@
+use entity::{passport, prelude::*};
+use fred::prelude::*;
+use id::{audit, db, kv, webauthn, wrap_error, APIError};
+use lambda_http::http::Method;
+use sea_orm::prelude::*;
+use vercel_runtime::{run, Body, Error, Request, Response, StatusCode};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    run(wrap_error!(handler)).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ChallengeRequest {
+    /// The passport that was just scanned to log in
+    id: i32,
+}
+
+/// Challenges the owner of a freshly scanned passport to sign in with a passkey. The browser's
+/// answer goes to `/api/authorize` as the `webauthn` parameter, in place of a TOTP code.
+pub async fn handler(req: Request) -> Result<Response<Body>, Error> {
+    if req.method() != Method::POST {
+        return Err("Invalid method".to_string().into());
+    }
+
+    let body: ChallengeRequest = match req.body() {
+        Body::Text(_) | Body::Empty => return Err("Invalid body".to_string().into()),
+        Body::Binary(b) => serde_json::from_slice(b)?,
+    };
+
+    let audit = audit::Event::new(&req, "passkey.challenge").subject("passport", body.id);
+
+    // Only hand out challenges for a login that is actually under way
+    let kv = kv().await?;
+    let ready: Option<bool> = kv.get(body.id).await?;
+    if ready != Some(true) {
+        let mut resp = Response::new(Body::Text("Passport not ready for auth".to_string()));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        return Ok(resp);
+    }
+
+    let db = db().await?;
+    let passport: passport::Model = Passport::find_by_id(body.id)
+        .one(&db)
+        .await?
+        .ok_or("Passport not found".to_string())?;
+
+    let audit = audit.actor(passport.owner_id);
+    let Some(challenge) = webauthn::start_authentication(&db, passport.owner_id).await? else {
+        audit.failure(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "No passkeys are set up",
+            code: "no_passkeys",
+        })?));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    };
+
+    audit.success(&db).await;
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&challenge)?.into())?)
+}
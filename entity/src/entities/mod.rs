@@ -2,10 +2,21 @@
 
 pub mod prelude;
 
+pub mod audit_event;
 pub mod auth_grant;
 pub mod auth_session;
 pub mod auth_token;
 pub mod ceremonies;
+pub mod ceremony_attendance;
+pub mod ceremony_waitlist;
+pub mod door_access;
+pub mod door_reader;
+pub mod door_rule;
+pub mod group_membership;
 pub mod passport;
+pub mod passport_status_change;
 pub mod sea_orm_active_enums;
+pub mod totp_recovery_code;
 pub mod user;
+pub mod user_group;
+pub mod webauthn_credential;
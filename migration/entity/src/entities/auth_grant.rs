@@ -14,12 +14,23 @@ pub struct Model {
     pub scope: Json,
     pub client_id: String,
     pub code: Option<String>,
+    pub passport_id: Option<i32>,
+    pub auth_time: Option<DateTimeWithTimeZone>,
+    pub amr: Option<Json>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::auth_token::Entity")]
     AuthToken,
+    #[sea_orm(
+        belongs_to = "super::passport::Entity",
+        from = "Column::PassportId",
+        to = "super::passport::Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    Passport,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::OwnerId",
@@ -36,6 +47,12 @@ impl Related<super::auth_token::Entity> for Entity {
     }
 }
 
+impl Related<super::passport::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Passport.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
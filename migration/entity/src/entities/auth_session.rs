@@ -11,10 +11,21 @@ pub struct Model {
     pub token: String,
     pub until: DateTimeWithTimeZone,
     pub owner_id: i32,
+    pub passport_id: Option<i32>,
+    pub auth_time: DateTimeWithTimeZone,
+    pub amr: Json,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::passport::Entity",
+        from = "Column::PassportId",
+        to = "super::passport::Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    Passport,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::OwnerId",
@@ -25,6 +36,12 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::passport::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Passport.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
@@ -6,18 +6,38 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "ceremonies")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
     pub ceremony_time: DateTime,
-    pub total_slots: i32,
+    pub capacity: i32,
     pub open_registration: bool,
+    pub cancelled_at: Option<DateTimeWithTimeZone>,
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    pub location: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::ceremony_attendance::Entity")]
+    CeremonyAttendance,
+    #[sea_orm(has_many = "super::ceremony_waitlist::Entity")]
+    CeremonyWaitlist,
     #[sea_orm(has_many = "super::passport::Entity")]
     Passport,
 }
 
+impl Related<super::ceremony_attendance::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CeremonyAttendance.def()
+    }
+}
+
+impl Related<super::ceremony_waitlist::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CeremonyWaitlist.def()
+    }
+}
+
 impl Related<super::passport::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Passport.def()
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0-rc.5
 
+use super::sea_orm_active_enums::PassportStatusEnum;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -16,20 +17,31 @@ pub struct Model {
     pub date_of_issue: Date,
     pub place_of_origin: String,
     pub secret: String,
-    pub activated: bool,
-    pub ceremony_time: DateTime,
+    pub status: PassportStatusEnum,
+    pub status_changed_at: DateTimeWithTimeZone,
+    pub ceremony_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::auth_grant::Entity")]
+    AuthGrant,
+    #[sea_orm(has_many = "super::auth_session::Entity")]
+    AuthSession,
     #[sea_orm(
         belongs_to = "super::ceremonies::Entity",
-        from = "Column::CeremonyTime",
-        to = "super::ceremonies::Column::CeremonyTime",
+        from = "Column::CeremonyId",
+        to = "super::ceremonies::Column::Id",
         on_update = "Cascade",
-        on_delete = "Cascade"
+        on_delete = "SetNull"
     )]
     Ceremonies,
+    #[sea_orm(has_many = "super::ceremony_attendance::Entity")]
+    CeremonyAttendance,
+    #[sea_orm(has_many = "super::ceremony_waitlist::Entity")]
+    CeremonyWaitlist,
+    #[sea_orm(has_many = "super::passport_status_change::Entity")]
+    PassportStatusChange,
     #[sea_orm(
         belongs_to = "super::user::Entity",
         from = "Column::OwnerId",
@@ -40,12 +52,42 @@ pub enum Relation {
     User,
 }
 
+impl Related<super::auth_grant::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthGrant.def()
+    }
+}
+
+impl Related<super::auth_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuthSession.def()
+    }
+}
+
 impl Related<super::ceremonies::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Ceremonies.def()
     }
 }
 
+impl Related<super::ceremony_attendance::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CeremonyAttendance.def()
+    }
+}
+
+impl Related<super::ceremony_waitlist::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CeremonyWaitlist.def()
+    }
+}
+
+impl Related<super::passport_status_change::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PassportStatusChange.def()
+    }
+}
+
 impl Related<super::user::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::User.def()
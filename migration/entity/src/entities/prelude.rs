@@ -1,8 +1,19 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0-rc.5
 
+pub use super::audit_event::Entity as AuditEvent;
 pub use super::auth_grant::Entity as AuthGrant;
 pub use super::auth_session::Entity as AuthSession;
 pub use super::auth_token::Entity as AuthToken;
 pub use super::ceremonies::Entity as Ceremonies;
+pub use super::ceremony_attendance::Entity as CeremonyAttendance;
+pub use super::ceremony_waitlist::Entity as CeremonyWaitlist;
+pub use super::door_access::Entity as DoorAccess;
+pub use super::door_reader::Entity as DoorReader;
+pub use super::door_rule::Entity as DoorRule;
+pub use super::group_membership::Entity as GroupMembership;
 pub use super::passport::Entity as Passport;
+pub use super::passport_status_change::Entity as PassportStatusChange;
+pub use super::totp_recovery_code::Entity as TotpRecoveryCode;
 pub use super::user::Entity as User;
+pub use super::user_group::Entity as UserGroup;
+pub use super::webauthn_credential::Entity as WebauthnCredential;
@@ -3,11 +3,93 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "audit_outcome_enum"
+)]
+pub enum AuditOutcomeEnum {
+    #[sea_orm(string_value = "denied")]
+    Denied,
+    #[sea_orm(string_value = "failure")]
+    Failure,
+    #[sea_orm(string_value = "success")]
+    Success,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "door_effect_enum"
+)]
+pub enum DoorEffectEnum {
+    #[sea_orm(string_value = "allow")]
+    Allow,
+    #[sea_orm(string_value = "deny")]
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "door_result_enum"
+)]
+pub enum DoorResultEnum {
+    #[sea_orm(string_value = "account_disabled")]
+    AccountDisabled,
+    #[sea_orm(string_value = "inactive_passport")]
+    InactivePassport,
+    #[sea_orm(string_value = "no_access")]
+    NoAccess,
+    #[sea_orm(string_value = "opened")]
+    Opened,
+    #[sea_orm(string_value = "unknown_passport")]
+    UnknownPassport,
+    #[sea_orm(string_value = "wrong_secret")]
+    WrongSecret,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "passport_status_enum"
+)]
+pub enum PassportStatusEnum {
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "expired")]
+    Expired,
+    #[sea_orm(string_value = "issued")]
+    Issued,
+    #[sea_orm(string_value = "lost")]
+    Lost,
+    #[sea_orm(string_value = "pending_ceremony")]
+    PendingCeremony,
+    #[sea_orm(string_value = "revoked")]
+    Revoked,
+    #[sea_orm(string_value = "suspended")]
+    Suspended,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "role_enum")]
 pub enum RoleEnum {
     #[sea_orm(string_value = "admin")]
     Admin,
+    #[sea_orm(string_value = "alumni")]
+    Alumni,
+    #[sea_orm(string_value = "ceremony_staff")]
+    CeremonyStaff,
+    #[sea_orm(string_value = "door_access")]
+    DoorAccess,
     #[sea_orm(string_value = "hacker")]
     Hacker,
+    #[sea_orm(string_value = "organizer")]
+    Organizer,
 }
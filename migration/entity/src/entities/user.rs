@@ -13,6 +13,12 @@ pub struct Model {
     pub discord_id: i64,
     pub role: RoleEnum,
     pub totp: Option<String>,
+    pub discord_username: Option<String>,
+    pub discord_avatar: Option<String>,
+    pub discord_verified_at: Option<DateTimeWithTimeZone>,
+    pub disabled_at: Option<DateTimeWithTimeZone>,
+    pub disabled_reason: Option<String>,
+    pub totp_last_step: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -21,8 +27,20 @@ pub enum Relation {
     AuthGrant,
     #[sea_orm(has_many = "super::auth_session::Entity")]
     AuthSession,
+    #[sea_orm(has_many = "super::ceremony_attendance::Entity")]
+    CeremonyAttendance,
+    #[sea_orm(has_many = "super::door_access::Entity")]
+    DoorAccess,
+    #[sea_orm(has_many = "super::group_membership::Entity")]
+    GroupMembership,
     #[sea_orm(has_many = "super::passport::Entity")]
     Passport,
+    #[sea_orm(has_many = "super::passport_status_change::Entity")]
+    PassportStatusChange,
+    #[sea_orm(has_many = "super::totp_recovery_code::Entity")]
+    TotpRecoveryCode,
+    #[sea_orm(has_many = "super::webauthn_credential::Entity")]
+    WebauthnCredential,
 }
 
 impl Related<super::auth_grant::Entity> for Entity {
@@ -37,10 +55,46 @@ impl Related<super::auth_session::Entity> for Entity {
     }
 }
 
+impl Related<super::ceremony_attendance::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CeremonyAttendance.def()
+    }
+}
+
+impl Related<super::door_access::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DoorAccess.def()
+    }
+}
+
+impl Related<super::group_membership::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::GroupMembership.def()
+    }
+}
+
 impl Related<super::passport::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Passport.def()
     }
 }
 
+impl Related<super::passport_status_change::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PassportStatusChange.def()
+    }
+}
+
+impl Related<super::totp_recovery_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TotpRecoveryCode.def()
+    }
+}
+
+impl Related<super::webauthn_credential::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WebauthnCredential.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
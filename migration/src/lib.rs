@@ -10,6 +10,24 @@ mod m20240907_031824_passport_ceremony_date;
 mod m20240920_155703_auth_session;
 mod m20240917_210754_ceremonies;
 mod m20240924_225432_passport_ceremony_fk;
+mod m20261018_000001_passport_status;
+mod m20261018_000002_auth_passport_origin;
+mod m20261018_000003_discord_profile;
+mod m20261018_000004_ceremony_cancellation;
+mod m20261018_000005_ceremony_waitlist;
+mod m20261018_000006_ceremony_attendance;
+mod m20261018_000007_ceremony_id;
+mod m20261018_000008_user_disabled;
+mod m20261018_000009_more_roles;
+mod m20261018_000010_groups;
+mod m20261018_000011_totp_recovery;
+mod m20261018_000012_webauthn;
+mod m20261018_000013_encrypt_totp;
+mod m20261018_000014_auth_time;
+mod m20261018_000015_audit_event;
+mod m20261018_000016_door_access;
+mod m20261018_000017_door_rule;
+mod m20261018_000018_door_reader;
 
 
 pub struct Migrator;
@@ -28,6 +46,24 @@ impl MigratorTrait for Migrator {
             Box::new(m20240920_155703_auth_session::Migration),
             Box::new(m20240917_210754_ceremonies::Migration),
             Box::new(m20240924_225432_passport_ceremony_fk::Migration),
+            Box::new(m20261018_000001_passport_status::Migration),
+            Box::new(m20261018_000002_auth_passport_origin::Migration),
+            Box::new(m20261018_000003_discord_profile::Migration),
+            Box::new(m20261018_000004_ceremony_cancellation::Migration),
+            Box::new(m20261018_000005_ceremony_waitlist::Migration),
+            Box::new(m20261018_000006_ceremony_attendance::Migration),
+            Box::new(m20261018_000007_ceremony_id::Migration),
+            Box::new(m20261018_000008_user_disabled::Migration),
+            Box::new(m20261018_000009_more_roles::Migration),
+            Box::new(m20261018_000010_groups::Migration),
+            Box::new(m20261018_000011_totp_recovery::Migration),
+            Box::new(m20261018_000012_webauthn::Migration),
+            Box::new(m20261018_000013_encrypt_totp::Migration),
+            Box::new(m20261018_000014_auth_time::Migration),
+            Box::new(m20261018_000015_audit_event::Migration),
+            Box::new(m20261018_000016_door_access::Migration),
+            Box::new(m20261018_000017_door_rule::Migration),
+            Box::new(m20261018_000018_door_reader::Migration),
         ]
     }
 }
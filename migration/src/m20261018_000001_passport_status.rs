@@ -0,0 +1,213 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    Id,
+    Activated,
+    Status,
+    StatusChangedAt,
+}
+
+#[derive(DeriveIden)]
+enum PassportStatusChange {
+    Table,
+    Id,
+    PassportId,
+    FromStatus,
+    ToStatus,
+    Reason,
+    ChangedBy,
+    ChangedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+struct PassportStatusEnum;
+
+#[derive(DeriveIden, EnumIter)]
+enum PassportStatusVariants {
+    Draft,
+    PendingCeremony,
+    Issued,
+    Active,
+    Suspended,
+    Lost,
+    Revoked,
+    Expired,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(PassportStatusEnum)
+                    .values(PassportStatusVariants::iter())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .add_column(
+                        ColumnDef::new(Passport::Status)
+                            .enumeration(PassportStatusEnum, PassportStatusVariants::iter())
+                            .default("draft")
+                            .not_null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(Passport::StatusChangedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PassportStatusChange::Table)
+                    .col(
+                        ColumnDef::new(PassportStatusChange::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PassportStatusChange::PassportId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PassportStatusChange::FromStatus)
+                            .enumeration(PassportStatusEnum, PassportStatusVariants::iter()),
+                    )
+                    .col(
+                        ColumnDef::new(PassportStatusChange::ToStatus)
+                            .enumeration(PassportStatusEnum, PassportStatusVariants::iter())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PassportStatusChange::Reason).string())
+                    .col(ColumnDef::new(PassportStatusChange::ChangedBy).integer())
+                    .col(
+                        ColumnDef::new(PassportStatusChange::ChangedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_passport_status_change_passport")
+                            .to(Passport::Table, Passport::Id)
+                            .from(
+                                PassportStatusChange::Table,
+                                PassportStatusChange::PassportId,
+                            )
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_passport_status_change_user")
+                            .to(User::Table, User::Id)
+                            .from(PassportStatusChange::Table, PassportStatusChange::ChangedBy)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Activated passports become active. Passports that were deactivated because a
+        // newer one was activated are revoked, and everything else is still waiting on
+        // its ceremony.
+        let db = manager.get_connection();
+        db.execute_unprepared(r#"UPDATE "passport" SET "status" = 'active' WHERE "activated""#)
+            .await?;
+        db.execute_unprepared(
+            r#"UPDATE "passport" AS "p" SET "status" = 'revoked'
+            WHERE NOT "p"."activated" AND EXISTS (
+                SELECT 1 FROM "passport" AS "n"
+                WHERE "n"."owner_id" = "p"."owner_id" AND "n"."id" > "p"."id" AND "n"."activated"
+            )"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"UPDATE "passport" SET "status" = 'pending_ceremony' WHERE "status" = 'draft'"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"INSERT INTO "passport_status_change" ("passport_id", "to_status", "reason")
+            SELECT "id", "status", 'migrated from activated flag' FROM "passport""#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .drop_column(Passport::Activated)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .add_column(
+                        ColumnDef::new(Passport::Activated)
+                            .boolean()
+                            .default(false)
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(r#"UPDATE "passport" SET "activated" = ("status" = 'active')"#)
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(PassportStatusChange::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .drop_column(Passport::Status)
+                    .drop_column(Passport::StatusChangedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(PassportStatusEnum).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
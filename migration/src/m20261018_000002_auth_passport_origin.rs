@@ -0,0 +1,91 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AuthGrant {
+    Table,
+    PassportId,
+}
+
+#[derive(DeriveIden)]
+enum AuthSession {
+    Table,
+    PassportId,
+}
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthGrant::Table)
+                    .add_column(ColumnDef::new(AuthGrant::PassportId).integer())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_auth_grant_passport")
+                            .to_tbl(Passport::Table)
+                            .to_col(Passport::Id)
+                            .from_tbl(AuthGrant::Table)
+                            .from_col(AuthGrant::PassportId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthSession::Table)
+                    .add_column(ColumnDef::new(AuthSession::PassportId).integer())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_auth_session_passport")
+                            .to_tbl(Passport::Table)
+                            .to_col(Passport::Id)
+                            .from_tbl(AuthSession::Table)
+                            .from_col(AuthSession::PassportId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthSession::Table)
+                    .drop_foreign_key(Alias::new("fk_auth_session_passport"))
+                    .drop_column(AuthSession::PassportId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthGrant::Table)
+                    .drop_foreign_key(Alias::new("fk_auth_grant_passport"))
+                    .drop_column(AuthGrant::PassportId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    DiscordUsername,
+    DiscordAvatar,
+    DiscordVerifiedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::DiscordUsername).string())
+                    .add_column(ColumnDef::new(User::DiscordAvatar).string())
+                    .add_column(ColumnDef::new(User::DiscordVerifiedAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(User::DiscordUsername)
+                    .drop_column(User::DiscordAvatar)
+                    .drop_column(User::DiscordVerifiedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,127 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    CeremonyTime,
+}
+
+#[derive(DeriveIden)]
+enum Ceremonies {
+    Table,
+    CeremonyTime,
+    CancelledAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .add_column(ColumnDef::new(Ceremonies::CancelledAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Passports without a ceremony are unassigned, and removing a ceremony unassigns
+        // its registrants instead of deleting their passports
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .drop_foreign_key(Alias::new("fk_ceremony_time"))
+                    .modify_column(ColumnDef::new(Passport::CeremonyTime).timestamp().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"ALTER TABLE "passport" ALTER COLUMN "ceremony_time" DROP DEFAULT"#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_ceremony_time")
+                            .to_tbl(Ceremonies::Table)
+                            .to_col(Ceremonies::CeremonyTime)
+                            .from_tbl(Passport::Table)
+                            .from_col(Passport::CeremonyTime)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .drop_foreign_key(Alias::new("fk_ceremony_time"))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Park unassigned passports on the placeholder ceremony the old default pointed at
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"INSERT INTO "ceremonies" ("ceremony_time", "total_slots", "open_registration")
+            VALUES ('1970-01-01 00:00:00', 0, false) ON CONFLICT DO NOTHING"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            r#"UPDATE "passport" SET "ceremony_time" = '1970-01-01 00:00:00'
+            WHERE "ceremony_time" IS NULL"#,
+        )
+        .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Passport::Table)
+                    .modify_column(
+                        ColumnDef::new(Passport::CeremonyTime)
+                            .timestamp()
+                            .default("1970-01-01 00:00:00")
+                            .not_null(),
+                    )
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_ceremony_time")
+                            .to_tbl(Ceremonies::Table)
+                            .to_col(Ceremonies::CeremonyTime)
+                            .from_tbl(Passport::Table)
+                            .from_col(Passport::CeremonyTime)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .drop_column(Ceremonies::CancelledAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum CeremonyWaitlist {
+    Table,
+    Id,
+    CeremonyTime,
+    PassportId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Ceremonies {
+    Table,
+    CeremonyTime,
+}
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CeremonyWaitlist::Table)
+                    .col(
+                        ColumnDef::new(CeremonyWaitlist::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CeremonyWaitlist::CeremonyTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    // A passport waits for one ceremony at a time
+                    .col(
+                        ColumnDef::new(CeremonyWaitlist::PassportId)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CeremonyWaitlist::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ceremony_waitlist_ceremony")
+                            .to(Ceremonies::Table, Ceremonies::CeremonyTime)
+                            .from(CeremonyWaitlist::Table, CeremonyWaitlist::CeremonyTime)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ceremony_waitlist_passport")
+                            .to(Passport::Table, Passport::Id)
+                            .from(CeremonyWaitlist::Table, CeremonyWaitlist::PassportId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_waitlist_order")
+                    .table(CeremonyWaitlist::Table)
+                    .col(CeremonyWaitlist::CeremonyTime)
+                    .col(CeremonyWaitlist::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CeremonyWaitlist::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
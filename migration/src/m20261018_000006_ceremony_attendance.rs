@@ -0,0 +1,116 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum CeremonyAttendance {
+    Table,
+    Id,
+    PassportId,
+    CeremonyTime,
+    CheckedInBy,
+    CheckedInAt,
+}
+
+#[derive(DeriveIden)]
+enum Ceremonies {
+    Table,
+    CeremonyTime,
+}
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CeremonyAttendance::Table)
+                    .col(
+                        ColumnDef::new(CeremonyAttendance::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CeremonyAttendance::PassportId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CeremonyAttendance::CeremonyTime)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CeremonyAttendance::CheckedInBy).integer())
+                    .col(
+                        ColumnDef::new(CeremonyAttendance::CheckedInAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ceremony_attendance_passport")
+                            .to(Passport::Table, Passport::Id)
+                            .from(CeremonyAttendance::Table, CeremonyAttendance::PassportId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ceremony_attendance_ceremony")
+                            .to(Ceremonies::Table, Ceremonies::CeremonyTime)
+                            .from(CeremonyAttendance::Table, CeremonyAttendance::CeremonyTime)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ceremony_attendance_user")
+                            .to(User::Table, User::Id)
+                            .from(CeremonyAttendance::Table, CeremonyAttendance::CheckedInBy)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Checking in twice for the same ceremony is a no-op
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_attendance_passport_ceremony")
+                    .table(CeremonyAttendance::Table)
+                    .col(CeremonyAttendance::PassportId)
+                    .col(CeremonyAttendance::CeremonyTime)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CeremonyAttendance::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Ceremonies {
+    Table,
+    Id,
+    CeremonyTime,
+    TotalSlots,
+    Capacity,
+    Name,
+    Location,
+}
+
+/// Columns of the tables that point at a ceremony
+#[derive(DeriveIden)]
+enum CeremonyRef {
+    CeremonyId,
+    CeremonyTime,
+}
+
+#[derive(DeriveIden)]
+enum Passport {
+    Table,
+    Id,
+    CeremonyId,
+    Status,
+}
+
+#[derive(DeriveIden)]
+enum PassportStatusChange {
+    Table,
+    PassportId,
+    FromStatus,
+    ToStatus,
+    Reason,
+}
+
+#[derive(DeriveIden)]
+enum CeremonyWaitlist {
+    Table,
+    Id,
+    CeremonyId,
+}
+
+#[derive(DeriveIden)]
+enum CeremonyAttendance {
+    Table,
+    PassportId,
+    CeremonyId,
+}
+
+#[derive(DeriveIden)]
+struct PassportStatusEnum;
+
+/// A table that points at a ceremony
+struct Reference {
+    table: &'static str,
+    /// Its foreign key to the ceremony's id
+    fk: &'static str,
+    /// Its foreign key to the ceremony's time, from before ceremonies had ids
+    time_fk: &'static str,
+    /// Whether it can be left without a ceremony
+    nullable: bool,
+    /// What happens to it when its ceremony is deleted
+    on_delete: ForeignKeyAction,
+}
+
+const REFERENCES: [Reference; 3] = [
+    Reference {
+        table: "passport",
+        fk: "fk_passport_ceremony",
+        time_fk: "fk_ceremony_time",
+        nullable: true,
+        on_delete: ForeignKeyAction::SetNull,
+    },
+    Reference {
+        table: "ceremony_waitlist",
+        fk: "fk_ceremony_waitlist_ceremony",
+        time_fk: "fk_ceremony_waitlist_ceremony",
+        nullable: false,
+        on_delete: ForeignKeyAction::Cascade,
+    },
+    Reference {
+        table: "ceremony_attendance",
+        fk: "fk_ceremony_attendance_ceremony",
+        time_fk: "fk_ceremony_attendance_ceremony",
+        nullable: false,
+        on_delete: ForeignKeyAction::Cascade,
+    },
+];
+
+/// Copies `to` on each row of `table` from the matching ceremony's `from`, joining the
+/// ceremony on `on` against the row's `by`
+fn copy_from_ceremony(
+    table: &str,
+    to: CeremonyRef,
+    from: Ceremonies,
+    on: Ceremonies,
+    by: CeremonyRef,
+) -> UpdateStatement {
+    Query::update()
+        .table(Alias::new(table))
+        .value(
+            to,
+            SimpleExpr::SubQuery(
+                None,
+                Box::new(
+                    Query::select()
+                        .column(from)
+                        .from(Ceremonies::Table)
+                        .and_where(
+                            Expr::col((Ceremonies::Table, on)).equals((Alias::new(table), by)),
+                        )
+                        .to_owned()
+                        .into_sub_query_statement(),
+                ),
+            ),
+        )
+        .to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .add_column(ColumnDef::new(Ceremonies::Id).integer().auto_increment())
+                    .add_column(
+                        ColumnDef::new(Ceremonies::Name)
+                            .string()
+                            .default("")
+                            .not_null(),
+                    )
+                    .add_column(ColumnDef::new(Ceremonies::Location).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .rename_column(Ceremonies::TotalSlots, Ceremonies::Capacity)
+                    .to_owned(),
+            )
+            .await?;
+        // sea-query can only set defaults, not drop them
+        db.execute_unprepared(
+            r#"ALTER TABLE "ceremonies" ALTER COLUMN "ceremony_time" DROP DEFAULT"#,
+        )
+        .await?;
+
+        // Point everything that referenced a ceremony by its time at its id instead
+        for Reference {
+            table,
+            time_fk,
+            nullable,
+            ..
+        } in REFERENCES
+        {
+            manager
+                .alter_table(
+                    TableAlterStatement::new()
+                        .table(Alias::new(table))
+                        .drop_foreign_key(Alias::new(time_fk))
+                        .add_column(ColumnDef::new(CeremonyRef::CeremonyId).integer())
+                        .to_owned(),
+                )
+                .await?;
+            db.execute(backend.build(&copy_from_ceremony(
+                table,
+                CeremonyRef::CeremonyId,
+                Ceremonies::Id,
+                Ceremonies::CeremonyTime,
+                CeremonyRef::CeremonyTime,
+            )))
+            .await?;
+
+            let mut alter = TableAlterStatement::new();
+            alter.table(Alias::new(table));
+            if !nullable {
+                alter.modify_column(ColumnDef::new(CeremonyRef::CeremonyId).integer().not_null());
+            }
+            manager
+                .alter_table(alter.drop_column(CeremonyRef::CeremonyTime).to_owned())
+                .await?;
+        }
+
+        // sea-query can't drop a primary key
+        db.execute_unprepared(
+            r#"ALTER TABLE "ceremonies" DROP CONSTRAINT "ceremonies_pkey", ADD PRIMARY KEY ("id")"#,
+        )
+        .await?;
+
+        for Reference {
+            table,
+            fk,
+            on_delete,
+            ..
+        } in REFERENCES
+        {
+            manager
+                .create_foreign_key(
+                    ForeignKey::create()
+                        .name(fk)
+                        .from(Alias::new(table), CeremonyRef::CeremonyId)
+                        .to(Ceremonies::Table, Ceremonies::Id)
+                        .on_delete(on_delete)
+                        .on_update(ForeignKeyAction::Cascade)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // Passports parked on the old 1970 placeholder were never really registered.
+        // Deleting it unassigns them, so they go back to being drafts like they would if
+        // their ceremony were cancelled.
+        db.execute(
+            backend.build(
+                Query::delete().from_table(Ceremonies::Table).and_where(
+                    Expr::col(Ceremonies::CeremonyTime)
+                        .eq(Expr::val("1970-01-01 00:00:00").cast_as(Alias::new("timestamp"))),
+                ),
+            ),
+        )
+        .await?;
+
+        let unassigned = Cond::all()
+            .add(Expr::col(Passport::CeremonyId).is_null())
+            .add(
+                Expr::col(Passport::Status)
+                    .eq(Expr::val("pending_ceremony").as_enum(PassportStatusEnum)),
+            );
+        db.execute(
+            backend.build(
+                Query::insert()
+                    .into_table(PassportStatusChange::Table)
+                    .columns([
+                        PassportStatusChange::PassportId,
+                        PassportStatusChange::FromStatus,
+                        PassportStatusChange::ToStatus,
+                        PassportStatusChange::Reason,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .column(Passport::Id)
+                            .column(Passport::Status)
+                            .expr(Expr::val("draft").as_enum(PassportStatusEnum))
+                            .expr(Expr::val("ceremony placeholder removed"))
+                            .from(Passport::Table)
+                            .cond_where(unassigned.clone())
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Migration(e.to_string()))?,
+            ),
+        )
+        .await?;
+        db.execute(
+            backend.build(
+                Query::update()
+                    .table(Passport::Table)
+                    .value(
+                        Passport::Status,
+                        Expr::val("draft").as_enum(PassportStatusEnum),
+                    )
+                    .cond_where(unassigned),
+            ),
+        )
+        .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_waitlist_order")
+                    .table(CeremonyWaitlist::Table)
+                    .col(CeremonyWaitlist::CeremonyId)
+                    .col(CeremonyWaitlist::Id)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_attendance_passport_ceremony")
+                    .table(CeremonyAttendance::Table)
+                    .col(CeremonyAttendance::PassportId)
+                    .col(CeremonyAttendance::CeremonyId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremonies_ceremony_time")
+                    .table(Ceremonies::Table)
+                    .col(Ceremonies::CeremonyTime)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Only works while no two ceremonies share a time
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_ceremonies_ceremony_time")
+                    .table(Ceremonies::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for Reference {
+            table,
+            fk,
+            nullable,
+            ..
+        } in REFERENCES
+        {
+            manager
+                .alter_table(
+                    TableAlterStatement::new()
+                        .table(Alias::new(table))
+                        .drop_foreign_key(Alias::new(fk))
+                        .add_column(ColumnDef::new(CeremonyRef::CeremonyTime).timestamp())
+                        .to_owned(),
+                )
+                .await?;
+            db.execute(backend.build(&copy_from_ceremony(
+                table,
+                CeremonyRef::CeremonyTime,
+                Ceremonies::CeremonyTime,
+                Ceremonies::Id,
+                CeremonyRef::CeremonyId,
+            )))
+            .await?;
+
+            let mut alter = TableAlterStatement::new();
+            alter.table(Alias::new(table));
+            if !nullable {
+                alter.modify_column(
+                    ColumnDef::new(CeremonyRef::CeremonyTime)
+                        .timestamp()
+                        .not_null(),
+                );
+            }
+            manager
+                .alter_table(alter.drop_column(CeremonyRef::CeremonyId).to_owned())
+                .await?;
+        }
+
+        // sea-query can't drop a primary key
+        db.execute_unprepared(
+            r#"ALTER TABLE "ceremonies" DROP CONSTRAINT "ceremonies_pkey",
+                ADD PRIMARY KEY ("ceremony_time")"#,
+        )
+        .await?;
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .modify_column(
+                        ColumnDef::new(Ceremonies::CeremonyTime)
+                            .default("1970-01-01T00:00:00.000Z"),
+                    )
+                    .drop_column(Ceremonies::Id)
+                    .drop_column(Ceremonies::Name)
+                    .drop_column(Ceremonies::Location)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(Ceremonies::Table)
+                    .rename_column(Ceremonies::Capacity, Ceremonies::TotalSlots)
+                    .to_owned(),
+            )
+            .await?;
+
+        for Reference {
+            table,
+            time_fk,
+            on_delete,
+            ..
+        } in REFERENCES
+        {
+            manager
+                .create_foreign_key(
+                    ForeignKey::create()
+                        .name(time_fk)
+                        .from(Alias::new(table), CeremonyRef::CeremonyTime)
+                        .to(Ceremonies::Table, Ceremonies::CeremonyTime)
+                        .on_delete(on_delete)
+                        .on_update(ForeignKeyAction::Cascade)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_waitlist_order")
+                    .table(CeremonyWaitlist::Table)
+                    .col(CeremonyRef::CeremonyTime)
+                    .col(CeremonyWaitlist::Id)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_ceremony_attendance_passport_ceremony")
+                    .table(CeremonyAttendance::Table)
+                    .col(CeremonyAttendance::PassportId)
+                    .col(CeremonyRef::CeremonyTime)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
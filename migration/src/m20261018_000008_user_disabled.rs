@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    DisabledAt,
+    DisabledReason,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::DisabledAt).timestamp_with_time_zone())
+                    .add_column(ColumnDef::new(User::DisabledReason).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(User::DisabledAt)
+                    .drop_column(User::DisabledReason)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,53 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+struct RoleEnum;
+
+#[derive(DeriveIden, EnumIter)]
+enum NewRoleVariants {
+    Organizer,
+    CeremonyStaff,
+    DoorAccess,
+    Alumni,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for variant in NewRoleVariants::iter() {
+            manager
+                .alter_type(Type::alter().name(RoleEnum).add_value(variant).to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Postgres can't drop enum values, so the type is rebuilt with everyone on a new role
+    /// demoted to hacker
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            r#"UPDATE "user" SET "role" = 'hacker' WHERE "role" NOT IN ('hacker', 'admin')"#,
+        )
+        .await?;
+        db.execute_unprepared(r#"ALTER TYPE "role_enum" RENAME TO "role_enum_old""#)
+            .await?;
+        db.execute_unprepared(r#"CREATE TYPE "role_enum" AS ENUM ('hacker', 'admin')"#)
+            .await?;
+        db.execute_unprepared(
+            r#"ALTER TABLE "user" ALTER COLUMN "role" TYPE "role_enum"
+            USING "role"::text::"role_enum""#,
+        )
+        .await?;
+        db.execute_unprepared(r#"DROP TYPE "role_enum_old""#)
+            .await?;
+
+        Ok(())
+    }
+}
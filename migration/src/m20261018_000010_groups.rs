@@ -0,0 +1,131 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum UserGroup {
+    Table,
+    Id,
+    Slug,
+    Name,
+    Description,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum GroupMembership {
+    Table,
+    Id,
+    GroupId,
+    UserId,
+    AddedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserGroup::Table)
+                    .col(
+                        ColumnDef::new(UserGroup::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(UserGroup::Slug)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(UserGroup::Name).string().not_null())
+                    .col(ColumnDef::new(UserGroup::Description).string())
+                    .col(
+                        ColumnDef::new(UserGroup::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupMembership::Table)
+                    .col(
+                        ColumnDef::new(GroupMembership::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(GroupMembership::GroupId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(GroupMembership::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(GroupMembership::AddedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_membership_group")
+                            .to(UserGroup::Table, UserGroup::Id)
+                            .from(GroupMembership::Table, GroupMembership::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_membership_user")
+                            .to(User::Table, User::Id)
+                            .from(GroupMembership::Table, GroupMembership::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_group_membership_group_user")
+                    .table(GroupMembership::Table)
+                    .col(GroupMembership::GroupId)
+                    .col(GroupMembership::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(GroupMembership::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(UserGroup::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    TotpLastStep,
+}
+
+#[derive(DeriveIden)]
+enum TotpRecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    CreatedAt,
+    UsedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(User::TotpLastStep).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TotpRecoveryCode::Table)
+                    .col(
+                        ColumnDef::new(TotpRecoveryCode::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TotpRecoveryCode::UserId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TotpRecoveryCode::CodeHash)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TotpRecoveryCode::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TotpRecoveryCode::UsedAt).timestamp_with_time_zone())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_totp_recovery_code_user")
+                            .to(User::Table, User::Id)
+                            .from(TotpRecoveryCode::Table, TotpRecoveryCode::UserId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_totp_recovery_code_user")
+                    .table(TotpRecoveryCode::Table)
+                    .col(TotpRecoveryCode::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TotpRecoveryCode::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(User::Table)
+                    .drop_column(User::TotpLastStep)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
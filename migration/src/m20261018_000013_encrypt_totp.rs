@@ -0,0 +1,214 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::prelude::*;
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, Statement},
+};
+use std::env;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+// A copy of the sealing format in `id::envelope`, kept here so this migration doesn't change
+// if that does
+
+const PREFIX: &str = "enc:v";
+const NONCE_LEN: usize = 12;
+
+fn keys() -> Result<Vec<(u32, Key<Aes256Gcm>)>, DbErr> {
+    let var = env::var("TFA_ENCRYPTION_KEYS")
+        .map_err(|_| DbErr::Custom("TFA_ENCRYPTION_KEYS is not set".to_string()))?;
+
+    var.split(',')
+        .map(|pair| {
+            let (version, key) = pair.trim().split_once(':').ok_or(DbErr::Custom(
+                "Encryption keys must be version:key pairs".to_string(),
+            ))?;
+            let key = BASE64_STANDARD
+                .decode(key)
+                .map_err(|e| DbErr::Custom(e.to_string()))?;
+            if key.len() != 32 {
+                return Err(DbErr::Custom(
+                    "Encryption keys must be 32 bytes".to_string(),
+                ));
+            }
+            let version = version
+                .parse()
+                .map_err(|_| DbErr::Custom("Key versions must be numbers".to_string()))?;
+
+            Ok((version, *Key::<Aes256Gcm>::from_slice(&key)))
+        })
+        .collect()
+}
+
+fn encrypt(key: &Key<Aes256Gcm>, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, DbErr> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| DbErr::Custom("Encryption failed".to_string()))?;
+
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt(key: &Key<Aes256Gcm>, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, DbErr> {
+    if sealed.len() < NONCE_LEN {
+        return Err(DbErr::Custom("Sealed value is too short".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    Aes256Gcm::new(key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| DbErr::Custom("Decryption failed".to_string()))
+}
+
+fn seal(keys: &[(u32, Key<Aes256Gcm>)], plaintext: &str, context: &str) -> Result<String, DbErr> {
+    let (version, kek) = keys.iter().max_by_key(|(v, _)| *v).ok_or(DbErr::Custom(
+        "No encryption keys are configured".to_string(),
+    ))?;
+
+    let dek = Aes256Gcm::generate_key(OsRng);
+    let wrapped = encrypt(kek, &dek, context.as_bytes())?;
+    let ciphertext = encrypt(&dek, plaintext.as_bytes(), context.as_bytes())?;
+
+    Ok(format!(
+        "{PREFIX}{version}:{}:{}",
+        BASE64_STANDARD.encode(wrapped),
+        BASE64_STANDARD.encode(ciphertext)
+    ))
+}
+
+fn open(keys: &[(u32, Key<Aes256Gcm>)], stored: &str, context: &str) -> Result<String, DbErr> {
+    let Some(rest) = stored.strip_prefix(PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let malformed = || DbErr::Custom("Malformed sealed value".to_string());
+
+    let mut parts = rest.splitn(3, ':');
+    let (Some(version), Some(wrapped), Some(ciphertext)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(malformed());
+    };
+    let version: u32 = version.parse().map_err(|_| malformed())?;
+    let (_, kek) = keys
+        .iter()
+        .find(|(v, _)| *v == version)
+        .ok_or(DbErr::Custom(format!(
+            "Encryption key version {version} is not configured"
+        )))?;
+
+    let dek = decrypt(
+        kek,
+        &BASE64_STANDARD.decode(wrapped).map_err(|_| malformed())?,
+        context.as_bytes(),
+    )?;
+    if dek.len() != 32 {
+        return Err(malformed());
+    }
+    let plaintext = decrypt(
+        Key::<Aes256Gcm>::from_slice(&dek),
+        &BASE64_STANDARD
+            .decode(ciphertext)
+            .map_err(|_| malformed())?,
+        context.as_bytes(),
+    )?;
+
+    String::from_utf8(plaintext).map_err(|_| malformed())
+}
+
+/// Rewrites every stored TOTP secret with `f`, which is given the context it's sealed for
+async fn rewrite(
+    manager: &SchemaManager<'_>,
+    f: impl Fn(&str, &str) -> Result<Option<String>, DbErr>,
+) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+
+    let rows = db
+        .query_all(Statement::from_string(
+            backend,
+            r#"SELECT id, totp FROM "user" WHERE totp IS NOT NULL"#,
+        ))
+        .await?;
+
+    for row in rows {
+        let id: i32 = row.try_get("", "id")?;
+        let totp: String = row.try_get("", "totp")?;
+
+        if let Some(totp) = f(&totp, &format!("user.totp:{id}"))? {
+            db.execute(Statement::from_sql_and_values(
+                backend,
+                r#"UPDATE "user" SET totp = $1 WHERE id = $2"#,
+                [totp.into(), id.into()],
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let plaintext = db
+            .query_one(Statement::from_string(
+                manager.get_database_backend(),
+                r#"SELECT 1 FROM "user" WHERE totp IS NOT NULL AND totp NOT LIKE 'enc:v%' LIMIT 1"#,
+            ))
+            .await?;
+        // Nothing to encrypt, so don't insist on a key
+        if plaintext.is_none() {
+            return Ok(());
+        }
+
+        let keys = keys()?;
+        rewrite(manager, |totp, context| {
+            if totp.starts_with(PREFIX) {
+                Ok(None)
+            } else {
+                seal(&keys, totp, context).map(Some)
+            }
+        })
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let sealed = db
+            .query_one(Statement::from_string(
+                manager.get_database_backend(),
+                r#"SELECT 1 FROM "user" WHERE totp LIKE 'enc:v%' LIMIT 1"#,
+            ))
+            .await?;
+        if sealed.is_none() {
+            return Ok(());
+        }
+
+        let keys = keys()?;
+        rewrite(manager, |totp, context| {
+            if totp.starts_with(PREFIX) {
+                open(&keys, totp, context).map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+        .await
+    }
+}
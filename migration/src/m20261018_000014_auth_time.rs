@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AuthSession {
+    Table,
+    AuthTime,
+    Amr,
+}
+
+#[derive(DeriveIden)]
+enum AuthGrant {
+    Table,
+    AuthTime,
+    Amr,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthSession::Table)
+                    .add_column(
+                        ColumnDef::new(AuthSession::AuthTime)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .add_column(
+                        ColumnDef::new(AuthSession::Amr)
+                            .json()
+                            .default("[]")
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Sessions last two months from the passport tap that made them
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"UPDATE auth_session SET auth_time = until - interval '2 months', amr = '["sc"]'"#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthGrant::Table)
+                    .add_column(ColumnDef::new(AuthGrant::AuthTime).timestamp_with_time_zone())
+                    .add_column(ColumnDef::new(AuthGrant::Amr).json())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthGrant::Table)
+                    .drop_column(AuthGrant::AuthTime)
+                    .drop_column(AuthGrant::Amr)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                TableAlterStatement::new()
+                    .table(AuthSession::Table)
+                    .drop_column(AuthSession::AuthTime)
+                    .drop_column(AuthSession::Amr)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,108 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AuditEvent {
+    Table,
+    Id,
+    CreatedAt,
+    Action,
+    Outcome,
+    ActorId,
+    Subject,
+    ClientId,
+    Ip,
+    UserAgent,
+    Detail,
+}
+
+#[derive(DeriveIden)]
+struct AuditOutcomeEnum;
+
+#[derive(DeriveIden, EnumIter)]
+enum AuditOutcomeVariants {
+    Success,
+    Failure,
+    Denied,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(AuditOutcomeEnum)
+                    .values(AuditOutcomeVariants::iter())
+                    .to_owned(),
+            )
+            .await?;
+
+        // No foreign keys, so the trail outlives whatever it talks about
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvent::Table)
+                    .col(
+                        ColumnDef::new(AuditEvent::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvent::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::Action).string().not_null())
+                    .col(
+                        ColumnDef::new(AuditEvent::Outcome)
+                            .enumeration(AuditOutcomeEnum, AuditOutcomeVariants::iter())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(AuditEvent::ActorId).integer())
+                    .col(ColumnDef::new(AuditEvent::Subject).string())
+                    .col(ColumnDef::new(AuditEvent::ClientId).string())
+                    .col(ColumnDef::new(AuditEvent::Ip).string())
+                    .col(ColumnDef::new(AuditEvent::UserAgent).string())
+                    .col(ColumnDef::new(AuditEvent::Detail).json())
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, col) in [
+            ("idx_audit_event_created_at", AuditEvent::CreatedAt),
+            ("idx_audit_event_actor", AuditEvent::ActorId),
+            ("idx_audit_event_subject", AuditEvent::Subject),
+            ("idx_audit_event_action", AuditEvent::Action),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(AuditEvent::Table)
+                        .col(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvent::Table).to_owned())
+            .await?;
+        manager
+            .drop_type(Type::drop().name(AuditOutcomeEnum).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
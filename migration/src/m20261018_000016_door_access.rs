@@ -0,0 +1,119 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum DoorAccess {
+    Table,
+    Id,
+    CreatedAt,
+    PassportId,
+    OwnerId,
+    ReaderId,
+    Result,
+    Reason,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+struct DoorResultEnum;
+
+#[derive(DeriveIden, EnumIter)]
+enum DoorResultVariants {
+    Opened,
+    UnknownPassport,
+    WrongSecret,
+    InactivePassport,
+    AccountDisabled,
+    NoAccess,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(DoorResultEnum)
+                    .values(DoorResultVariants::iter())
+                    .to_owned(),
+            )
+            .await?;
+
+        // The passport id is whatever was tapped, so it has no foreign key and unknown
+        // passports still get logged
+        manager
+            .create_table(
+                Table::create()
+                    .table(DoorAccess::Table)
+                    .col(
+                        ColumnDef::new(DoorAccess::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DoorAccess::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DoorAccess::PassportId).integer().not_null())
+                    .col(ColumnDef::new(DoorAccess::OwnerId).integer())
+                    .col(ColumnDef::new(DoorAccess::ReaderId).string())
+                    .col(
+                        ColumnDef::new(DoorAccess::Result)
+                            .enumeration(DoorResultEnum, DoorResultVariants::iter())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DoorAccess::Reason).string())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_door_access_owner")
+                            .to(User::Table, User::Id)
+                            .from(DoorAccess::Table, DoorAccess::OwnerId)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, col) in [
+            ("idx_door_access_created_at", DoorAccess::CreatedAt),
+            ("idx_door_access_owner", DoorAccess::OwnerId),
+            ("idx_door_access_passport", DoorAccess::PassportId),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(DoorAccess::Table)
+                        .col(col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DoorAccess::Table).to_owned())
+            .await?;
+        manager
+            .drop_type(Type::drop().name(DoorResultEnum).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
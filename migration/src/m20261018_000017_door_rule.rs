@@ -0,0 +1,162 @@
+use sea_orm::{EnumIter, Iterable};
+use sea_orm_migration::{prelude::*, sea_query::extension::postgres::Type};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum DoorRule {
+    Table,
+    Id,
+    Door,
+    Priority,
+    Effect,
+    Role,
+    GroupId,
+    Weekdays,
+    StartTime,
+    EndTime,
+    StartsOn,
+    EndsOn,
+    Description,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum DoorAccess {
+    Table,
+    RuleId,
+}
+
+#[derive(DeriveIden)]
+enum UserGroup {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+struct RoleEnum;
+
+#[derive(DeriveIden)]
+struct DoorEffectEnum;
+
+#[derive(DeriveIden, EnumIter)]
+enum DoorEffectVariants {
+    Allow,
+    Deny,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(DoorEffectEnum)
+                    .values(DoorEffectVariants::iter())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every condition left null matches anything, so a rule with only an effect covers
+        // everyone at every door all the time
+        manager
+            .create_table(
+                Table::create()
+                    .table(DoorRule::Table)
+                    .col(
+                        ColumnDef::new(DoorRule::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DoorRule::Door).string())
+                    .col(
+                        ColumnDef::new(DoorRule::Priority)
+                            .integer()
+                            .default(0)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DoorRule::Effect)
+                            .enumeration(DoorEffectEnum, DoorEffectVariants::iter())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DoorRule::Role).custom(RoleEnum))
+                    .col(ColumnDef::new(DoorRule::GroupId).integer())
+                    .col(ColumnDef::new(DoorRule::Weekdays).small_integer())
+                    .col(ColumnDef::new(DoorRule::StartTime).time())
+                    .col(ColumnDef::new(DoorRule::EndTime).time())
+                    .col(ColumnDef::new(DoorRule::StartsOn).date())
+                    .col(ColumnDef::new(DoorRule::EndsOn).date())
+                    .col(ColumnDef::new(DoorRule::Description).string())
+                    .col(
+                        ColumnDef::new(DoorRule::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_door_rule_group")
+                            .to(UserGroup::Table, UserGroup::Id)
+                            .from(DoorRule::Table, DoorRule::GroupId)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_door_rule_door")
+                    .table(DoorRule::Table)
+                    .col(DoorRule::Door)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DoorAccess::Table)
+                    .add_column(ColumnDef::new(DoorAccess::RuleId).integer())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk_door_access_rule")
+                            .from_tbl(DoorAccess::Table)
+                            .from_col(DoorAccess::RuleId)
+                            .to_tbl(DoorRule::Table)
+                            .to_col(DoorRule::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DoorAccess::Table)
+                    .drop_column(DoorAccess::RuleId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(DoorRule::Table).to_owned())
+            .await?;
+        manager
+            .drop_type(Type::drop().name(DoorEffectEnum).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum DoorReader {
+    Table,
+    Id,
+    Slug,
+    Name,
+    Secret,
+    CreatedAt,
+    LastSeenAt,
+    DisabledAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DoorReader::Table)
+                    .col(
+                        ColumnDef::new(DoorReader::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DoorReader::Slug)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(DoorReader::Name).string())
+                    .col(ColumnDef::new(DoorReader::Secret).string().not_null())
+                    .col(
+                        ColumnDef::new(DoorReader::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .default(Expr::current_timestamp())
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(DoorReader::LastSeenAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(DoorReader::DisabledAt).timestamp_with_time_zone())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DoorReader::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
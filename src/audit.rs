@@ -0,0 +1,126 @@
+use std::fmt::Display;
+
+use entity::{audit_event, sea_orm_active_enums::AuditOutcomeEnum};
+use lambda_http::http::header::USER_AGENT;
+use oxide_auth::endpoint::Scope;
+use sea_orm::{prelude::*, ActiveValue};
+use vercel_runtime::Request;
+
+/// Something that happened, ready to be written to the audit log once its outcome is known
+#[derive(Debug, Clone)]
+pub struct Event {
+    action: &'static str,
+    actor_id: Option<i32>,
+    subject: Option<String>,
+    client_id: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    detail: Option<serde_json::Value>,
+}
+
+fn header(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+impl Event {
+    /// Starts an event for `action`, taking the caller's address and user agent from the request
+    pub fn new(req: &Request, action: &'static str) -> Self {
+        // The first forwarded address is the client, the rest are proxies
+        let ip = header(req, "x-forwarded-for")
+            .and_then(|f| f.split(',').next().map(|ip| ip.trim().to_string()))
+            .or_else(|| header(req, "x-real-ip"));
+
+        Self {
+            action,
+            actor_id: None,
+            subject: None,
+            client_id: None,
+            ip,
+            user_agent: header(req, USER_AGENT.as_str()),
+            detail: None,
+        }
+    }
+
+    /// Who did it
+    pub fn actor(mut self, user_id: i32) -> Self {
+        self.actor_id = Some(user_id);
+        self
+    }
+
+    /// What it was done to, such as `passport:12`
+    pub fn subject(mut self, kind: &str, id: impl Display) -> Self {
+        self.subject = Some(format!("{kind}:{id}"));
+        self
+    }
+
+    /// Which OAuth client it went through
+    pub fn client(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    pub fn detail(mut self, detail: serde_json::Value) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    pub async fn success<C: ConnectionTrait>(&self, db: &C) {
+        self.record(db, AuditOutcomeEnum::Success).await
+    }
+
+    /// It was attempted and didn't work, like a wrong TOTP code
+    pub async fn failure<C: ConnectionTrait>(&self, db: &C) {
+        self.record(db, AuditOutcomeEnum::Failure).await
+    }
+
+    /// It was refused for lack of permission
+    pub async fn denied<C: ConnectionTrait>(&self, db: &C) {
+        self.record(db, AuditOutcomeEnum::Denied).await
+    }
+
+    /// Checks the request's token like [`crate::oauth_grant`], recording the event as denied
+    /// when it doesn't carry a valid one with the scopes
+    pub async fn oauth_grant(
+        &self,
+        req: Request,
+        scopes: Vec<Scope>,
+    ) -> Result<(i32, Scope), vercel_runtime::Error> {
+        let grant = crate::oauth_grant(req, scopes).await;
+        if grant.is_err() {
+            if let Ok(db) = crate::db().await {
+                self.denied(&db).await;
+            }
+        }
+        grant
+    }
+
+    /// Like [`Event::oauth_grant`], but only returns the token's owner
+    pub async fn oauth_user(
+        &self,
+        req: Request,
+        scopes: Vec<Scope>,
+    ) -> Result<i32, vercel_runtime::Error> {
+        Ok(self.oauth_grant(req, scopes).await?.0)
+    }
+
+    /// Writes the event. Failing to do so doesn't fail the request it describes.
+    pub async fn record<C: ConnectionTrait>(&self, db: &C, outcome: AuditOutcomeEnum) {
+        let event = audit_event::ActiveModel {
+            id: ActiveValue::NotSet,
+            created_at: ActiveValue::NotSet,
+            action: ActiveValue::Set(self.action.to_string()),
+            outcome: ActiveValue::Set(outcome),
+            actor_id: ActiveValue::Set(self.actor_id),
+            subject: ActiveValue::Set(self.subject.clone()),
+            client_id: ActiveValue::Set(self.client_id.clone()),
+            ip: ActiveValue::Set(self.ip.clone()),
+            user_agent: ActiveValue::Set(self.user_agent.clone()),
+            detail: ActiveValue::Set(self.detail.clone()),
+        };
+
+        let _ = event.insert(db).await;
+    }
+}
@@ -0,0 +1,494 @@
+use std::{collections::HashMap, env, str::FromStr};
+
+use chrono::Utc;
+use entity::{
+    ceremonies, ceremony_attendance, ceremony_waitlist, passport, prelude::*,
+    sea_orm_active_enums::PassportStatusEnum,
+};
+use fred::prelude::*;
+use sea_orm::{
+    prelude::*, ActiveValue, IntoActiveModel, PaginatorTrait, QueryOrder, QuerySelect,
+    TransactionTrait,
+};
+use vercel_runtime::{Body, Response, StatusCode};
+
+use crate::{kv, lifecycle, APIError};
+
+/// How long a held seat is kept for someone who hasn't finished registering, in seconds
+pub const HOLD_SECONDS: i64 = 10 * 60;
+
+/// Why someone can't take a seat at a ceremony
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    NotFound,
+    Cancelled,
+    Closed,
+    Full,
+}
+
+impl Unavailable {
+    pub fn response(&self) -> Response<Body> {
+        let (status, error) = match self {
+            Unavailable::NotFound => (
+                StatusCode::NOT_FOUND,
+                APIError {
+                    message: "There is no such ceremony",
+                    code: "ceremony_not_found",
+                },
+            ),
+            Unavailable::Cancelled => (
+                StatusCode::GONE,
+                APIError {
+                    message: "This ceremony has been cancelled",
+                    code: "ceremony_cancelled",
+                },
+            ),
+            Unavailable::Closed => (
+                StatusCode::CONFLICT,
+                APIError {
+                    message: "Registration for this ceremony is closed",
+                    code: "ceremony_closed",
+                },
+            ),
+            Unavailable::Full => (
+                StatusCode::CONFLICT,
+                APIError {
+                    message: "This ceremony is full",
+                    code: "ceremony_full",
+                },
+            ),
+        };
+
+        let mut resp = Response::new(Body::Text(
+            serde_json::to_string(&error).expect("API error to serialize"),
+        ));
+        *resp.status_mut() = status;
+        resp
+    }
+}
+
+/// Parses a ceremony time given either as a plain or an RFC 3339 timestamp
+pub fn parse_time(s: &str) -> Result<ChronoDateTime, vercel_runtime::Error> {
+    if let Ok(datetime) = ChronoDateTime::from_str(s) {
+        return Ok(datetime);
+    }
+    Ok(ChronoDateTime::parse_from_str(s, "%+")?)
+}
+
+/// Which ceremony a request means, given by id or, for older clients, by its time. A time
+/// only works as long as no other ceremony shares it.
+pub async fn resolve(
+    db: &DatabaseConnection,
+    id: Option<i32>,
+    time: Option<&str>,
+) -> Result<Result<i32, Unavailable>, vercel_runtime::Error> {
+    if let Some(id) = id {
+        return Ok(Ok(id));
+    }
+
+    let time = parse_time(time.ok_or("Ceremony ID required".to_string())?)?;
+    let mut found: Vec<ceremonies::Model> = Ceremonies::find()
+        .filter(ceremonies::Column::CeremonyTime.eq(time))
+        .all(db)
+        .await?;
+
+    match found.len() {
+        0 => Ok(Err(Unavailable::NotFound)),
+        1 => Ok(Ok(found.remove(0).id)),
+        _ => Err("Several ceremonies share that time, give a ceremony ID"
+            .to_string()
+            .into()),
+    }
+}
+
+fn holds_key(ceremony_id: i32) -> String {
+    format!("ceremony-holds:{ceremony_id}")
+}
+
+/// Seats filled by passports registered for the ceremony, not counting `except_owner`'s
+pub async fn seats_taken<C: ConnectionTrait>(
+    db: &C,
+    ceremony_id: i32,
+    except_owner: Option<i32>,
+) -> Result<u64, vercel_runtime::Error> {
+    let mut query = Passport::find()
+        .filter(passport::Column::CeremonyId.eq(ceremony_id))
+        .filter(passport::Column::Status.ne(PassportStatusEnum::Revoked));
+    if let Some(owner) = except_owner {
+        query = query.filter(passport::Column::OwnerId.ne(owner));
+    }
+
+    Ok(query.count(db).await?)
+}
+
+/// Seats held by people partway through registering, not counting `except_user`'s
+pub async fn seats_held(
+    ceremony_id: i32,
+    except_user: Option<i32>,
+) -> Result<u64, vercel_runtime::Error> {
+    held(&kv().await?, ceremony_id, except_user).await
+}
+
+async fn held(
+    kv: &RedisClient,
+    ceremony_id: i32,
+    except_user: Option<i32>,
+) -> Result<u64, vercel_runtime::Error> {
+    let key = holds_key(ceremony_id);
+    let now = Utc::now().timestamp() as f64;
+
+    kv.zremrangebyscore::<(), _, _, _>(&key, "-inf", now)
+        .await?;
+    let holders: Vec<i32> = kv.zrangebyscore(&key, now, "+inf", false, None).await?;
+
+    Ok(holders
+        .into_iter()
+        .filter(|h| Some(*h) != except_user)
+        .count() as u64)
+}
+
+/// Seats taken or held at each of the ceremonies, for listing many at once
+pub async fn seats_in_use(
+    db: &DatabaseConnection,
+    ceremony_ids: &[i32],
+) -> Result<HashMap<i32, u64>, vercel_runtime::Error> {
+    let taken: Vec<(Option<i32>, i64)> = Passport::find()
+        .select_only()
+        .column(passport::Column::CeremonyId)
+        .column_as(passport::Column::Id.count(), "taken")
+        .filter(passport::Column::CeremonyId.is_in(ceremony_ids.iter().copied()))
+        .filter(passport::Column::Status.ne(PassportStatusEnum::Revoked))
+        .group_by(passport::Column::CeremonyId)
+        .into_tuple()
+        .all(db)
+        .await?;
+    let mut seats: HashMap<i32, u64> = taken
+        .into_iter()
+        .filter_map(|(id, taken)| Some((id?, taken as u64)))
+        .collect();
+
+    let kv = kv().await?;
+    for id in ceremony_ids {
+        *seats.entry(*id).or_default() += held(&kv, *id, None).await?;
+    }
+
+    Ok(seats)
+}
+
+/// Checks that `user_id` could take a seat at the ceremony right now. The ceremony row is
+/// locked, so inside a transaction nobody else can take the seat until it commits.
+pub async fn check<C: ConnectionTrait>(
+    db: &C,
+    ceremony_id: i32,
+    user_id: i32,
+) -> Result<Result<ceremonies::Model, Unavailable>, vercel_runtime::Error> {
+    let Some(ceremony) = Ceremonies::find_by_id(ceremony_id)
+        .lock_exclusive()
+        .one(db)
+        .await?
+    else {
+        return Ok(Err(Unavailable::NotFound));
+    };
+
+    if ceremony.cancelled_at.is_some() {
+        return Ok(Err(Unavailable::Cancelled));
+    }
+    if !ceremony.open_registration || ceremony.ceremony_time <= Utc::now().naive_utc() {
+        return Ok(Err(Unavailable::Closed));
+    }
+
+    let taken = seats_taken(db, ceremony_id, Some(user_id)).await?
+        + seats_held(ceremony_id, Some(user_id)).await?;
+    if taken >= ceremony.capacity.max(0) as u64 {
+        return Ok(Err(Unavailable::Full));
+    }
+
+    Ok(Ok(ceremony))
+}
+
+/// Holds a seat for `user_id` for a few minutes, returning when the hold runs out
+pub async fn hold(
+    db: &DatabaseConnection,
+    ceremony_id: i32,
+    user_id: i32,
+) -> Result<Result<DateTimeUtc, Unavailable>, vercel_runtime::Error> {
+    // The ceremony stays locked until the hold is in place, so two people can't both
+    // take the last seat
+    let txn = db.begin().await?;
+    if let Err(unavailable) = check(&txn, ceremony_id, user_id).await? {
+        return Ok(Err(unavailable));
+    }
+
+    let until = Utc::now() + chrono::Duration::seconds(HOLD_SECONDS);
+
+    let kv = kv().await?;
+    let key = holds_key(ceremony_id);
+    kv.zadd::<(), _, _>(
+        &key,
+        None,
+        None,
+        false,
+        false,
+        (until.timestamp() as f64, user_id),
+    )
+    .await?;
+    kv.expire::<(), _>(&key, HOLD_SECONDS).await?;
+    txn.commit().await?;
+
+    Ok(Ok(until))
+}
+
+/// Gives back a held seat, usually because the registration went through
+pub async fn release(ceremony_id: i32, user_id: i32) -> Result<(), vercel_runtime::Error> {
+    let kv = kv().await?;
+    kv.zrem::<(), _, _>(holds_key(ceremony_id), user_id).await?;
+
+    Ok(())
+}
+
+/// Cancels a ceremony, unassigning everyone registered for it. Returns how many passports
+/// were unassigned.
+pub async fn cancel(
+    db: &DatabaseConnection,
+    ceremony: ceremonies::Model,
+    actor: Option<i32>,
+) -> Result<usize, vercel_runtime::Error> {
+    let txn = db.begin().await?;
+
+    // Passports already made at this ceremony stay linked to it
+    let registrants: Vec<passport::Model> = Passport::find()
+        .filter(passport::Column::CeremonyId.eq(ceremony.id))
+        .filter(passport::Column::Status.is_in([
+            PassportStatusEnum::Draft,
+            PassportStatusEnum::PendingCeremony,
+        ]))
+        .all(&txn)
+        .await?;
+    let unassigned = registrants.len();
+
+    for registrant in registrants {
+        let registrant = if registrant.status == PassportStatusEnum::PendingCeremony {
+            lifecycle::transition(
+                &txn,
+                registrant,
+                PassportStatusEnum::Draft,
+                Some("ceremony cancelled".to_string()),
+                actor,
+            )
+            .await?
+        } else {
+            registrant
+        };
+
+        let mut am = registrant.into_active_model();
+        am.ceremony_id = ActiveValue::Set(None);
+        am.update(&txn).await?;
+    }
+
+    // Nobody is getting a seat at it anymore
+    CeremonyWaitlist::delete_many()
+        .filter(ceremony_waitlist::Column::CeremonyId.eq(ceremony.id))
+        .exec(&txn)
+        .await?;
+
+    let mut am = ceremony.into_active_model();
+    am.open_registration = ActiveValue::Set(false);
+    am.cancelled_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+    am.update(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(unassigned)
+}
+
+/// Where the entry is in its ceremony's waitlist, counting from 1
+pub async fn waitlist_position<C: ConnectionTrait>(
+    db: &C,
+    entry: &ceremony_waitlist::Model,
+) -> Result<u64, vercel_runtime::Error> {
+    Ok(CeremonyWaitlist::find()
+        .filter(ceremony_waitlist::Column::CeremonyId.eq(entry.ceremony_id))
+        .filter(ceremony_waitlist::Column::Id.lte(entry.id))
+        .count(db)
+        .await?)
+}
+
+/// The waitlist entry for a passport, if it's waiting on a ceremony
+pub async fn waitlist_entry<C: ConnectionTrait>(
+    db: &C,
+    passport_id: i32,
+) -> Result<Option<ceremony_waitlist::Model>, vercel_runtime::Error> {
+    Ok(CeremonyWaitlist::find()
+        .filter(ceremony_waitlist::Column::PassportId.eq(passport_id))
+        .one(db)
+        .await?)
+}
+
+/// Puts a passport at the back of the ceremony's waitlist, giving up any place it had on
+/// another one. Waiting again on the same ceremony keeps the original place.
+pub async fn join_waitlist<C: ConnectionTrait>(
+    db: &C,
+    ceremony_id: i32,
+    passport_id: i32,
+) -> Result<ceremony_waitlist::Model, vercel_runtime::Error> {
+    if let Some(entry) = waitlist_entry(db, passport_id).await? {
+        if entry.ceremony_id == ceremony_id {
+            return Ok(entry);
+        }
+        entry.delete(db).await?;
+    }
+
+    Ok(ceremony_waitlist::ActiveModel {
+        id: ActiveValue::NotSet,
+        ceremony_id: ActiveValue::Set(ceremony_id),
+        passport_id: ActiveValue::Set(passport_id),
+        created_at: ActiveValue::Set(Utc::now().fixed_offset()),
+    }
+    .insert(db)
+    .await?)
+}
+
+/// Takes a passport off whichever waitlist it's on
+pub async fn leave_waitlist<C: ConnectionTrait>(
+    db: &C,
+    passport_id: i32,
+) -> Result<(), vercel_runtime::Error> {
+    CeremonyWaitlist::delete_many()
+        .filter(ceremony_waitlist::Column::PassportId.eq(passport_id))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Fills free seats at a ceremony from its waitlist in sign-up order. Promoted passports
+/// give up their old seat, which is then filled from that ceremony's waitlist in turn.
+/// Returns the passports that were promoted.
+pub async fn promote(
+    db: &DatabaseConnection,
+    ceremony_id: i32,
+) -> Result<Vec<passport::Model>, vercel_runtime::Error> {
+    let mut freed = vec![ceremony_id];
+    let mut promoted = Vec::new();
+
+    while let Some(id) = freed.pop() {
+        loop {
+            // Registrations lock the same row, so the seat can't be taken in the meantime
+            let txn = db.begin().await?;
+            let Some(ceremony) = Ceremonies::find_by_id(id)
+                .lock_exclusive()
+                .one(&txn)
+                .await?
+            else {
+                break;
+            };
+            // Nobody gets moved into a ceremony they couldn't register for themselves
+            if ceremony.cancelled_at.is_some()
+                || !ceremony.open_registration
+                || ceremony.ceremony_time <= Utc::now().naive_utc()
+            {
+                break;
+            }
+
+            let taken = seats_taken(&txn, id, None).await? + seats_held(id, None).await?;
+            if taken >= ceremony.capacity.max(0) as u64 {
+                break;
+            }
+
+            let Some(entry) = CeremonyWaitlist::find()
+                .filter(ceremony_waitlist::Column::CeremonyId.eq(id))
+                .order_by_asc(ceremony_waitlist::Column::Id)
+                .one(&txn)
+                .await?
+            else {
+                break;
+            };
+
+            let passport_id = entry.passport_id;
+            entry.delete(&txn).await?;
+
+            // Passports that have moved on since joining just lose their place
+            let Some(passport) = Passport::find_by_id(passport_id)
+                .one(&txn)
+                .await?
+                .filter(|p| lifecycle::is_editable(&p.status))
+            else {
+                txn.commit().await?;
+                continue;
+            };
+
+            let previous = passport.ceremony_id;
+            let passport = if passport.status == PassportStatusEnum::Draft {
+                lifecycle::transition(
+                    &txn,
+                    passport,
+                    PassportStatusEnum::PendingCeremony,
+                    Some("promoted from waitlist".to_string()),
+                    None,
+                )
+                .await?
+            } else {
+                passport
+            };
+
+            let mut am = passport.into_active_model();
+            am.ceremony_id = ActiveValue::Set(Some(id));
+            let passport = am.update(&txn).await?;
+
+            txn.commit().await?;
+
+            if let Some(previous) = previous.filter(|p| *p != id) {
+                freed.push(previous);
+            }
+            promoted.push(passport);
+        }
+    }
+
+    Ok(promoted)
+}
+
+/// Whether passports have to be checked in at a ceremony before they can be activated,
+/// set with `REQUIRE_CEREMONY_ATTENDANCE`
+pub fn attendance_required() -> bool {
+    env::var("REQUIRE_CEREMONY_ATTENDANCE").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// When the passport was checked in at a ceremony, if it ever was
+pub async fn attendance<C: ConnectionTrait>(
+    db: &C,
+    passport: &passport::Model,
+) -> Result<Option<ceremony_attendance::Model>, vercel_runtime::Error> {
+    Ok(CeremonyAttendance::find()
+        .filter(ceremony_attendance::Column::PassportId.eq(passport.id))
+        .order_by_desc(ceremony_attendance::Column::CheckedInAt)
+        .one(db)
+        .await?)
+}
+
+/// Marks a passport as present at the ceremony it's registered for. Checking in again
+/// keeps the original time.
+pub async fn check_in<C: ConnectionTrait>(
+    db: &C,
+    passport: &passport::Model,
+    ceremony_id: i32,
+    actor: Option<i32>,
+) -> Result<ceremony_attendance::Model, vercel_runtime::Error> {
+    if let Some(existing) = CeremonyAttendance::find()
+        .filter(ceremony_attendance::Column::PassportId.eq(passport.id))
+        .filter(ceremony_attendance::Column::CeremonyId.eq(ceremony_id))
+        .one(db)
+        .await?
+    {
+        return Ok(existing);
+    }
+
+    Ok(ceremony_attendance::ActiveModel {
+        id: ActiveValue::NotSet,
+        passport_id: ActiveValue::Set(passport.id),
+        ceremony_id: ActiveValue::Set(ceremony_id),
+        checked_in_by: ActiveValue::Set(actor),
+        checked_in_at: ActiveValue::Set(Utc::now().fixed_offset()),
+    }
+    .insert(db)
+    .await?)
+}
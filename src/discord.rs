@@ -0,0 +1,316 @@
+use std::env;
+
+use chrono::Utc;
+use entity::{prelude::*, sea_orm_active_enums::RoleEnum, user};
+use fred::prelude::*;
+use rand::distributions::{Alphanumeric, DistString};
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, TryIntoModel};
+use serde::Deserialize;
+use vercel_runtime::Request;
+
+use crate::{constant_time_eq, cookie, kv};
+
+/// Cookie holding the link token handed out once a Discord account is verified
+pub const LINK_COOKIE: &str = "discord_link";
+
+/// How long a link token stays usable, in seconds
+pub const LINK_TTL: i64 = 60 * 60;
+
+/// Cookie tying an OAuth flow to the browser that started it
+pub const STATE_COOKIE: &str = "discord_state";
+
+/// How long the browser has to come back from Discord, in seconds
+pub const STATE_TTL: i64 = 600;
+
+/// Base URL of the Discord API, overridable so the flow can run against a local mock
+fn api_url() -> String {
+    env::var("DISCORD_API_URL").unwrap_or_else(|_| "https://discord.com/api".to_string())
+}
+
+fn redirect_uri() -> String {
+    env::var("DISCORD_REDIRECT_URI")
+        .unwrap_or_else(|_| "https://id.purduehackers.com/api/discord".to_string())
+}
+
+fn client_id() -> String {
+    env::var("DISCORD_CLIENT_ID").expect("DISCORD_CLIENT_ID env var to be present")
+}
+
+/// Where and as whom to talk to Discord's OAuth endpoints
+#[derive(Debug, Clone)]
+pub struct Provider {
+    pub api_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+}
+
+impl Provider {
+    pub fn from_env() -> Self {
+        Self {
+            api_url: api_url(),
+            client_id: client_id(),
+            client_secret: env::var("DISCORD_CLIENT_SECRET")
+                .expect("DISCORD_CLIENT_SECRET env var to be present"),
+            redirect_uri: redirect_uri(),
+        }
+    }
+
+    /// Trades an authorization code for a token and asks Discord whose it is
+    pub async fn profile(&self, code: &str) -> Result<DiscordUser, vercel_runtime::Error> {
+        let client = reqwest::Client::new();
+
+        let token: TokenResponse = client
+            .post(format!("{}/oauth2/token", self.api_url))
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(client
+            .get(format!("{}/users/@me", self.api_url))
+            .bearer_auth(token.access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscordUser {
+    pub id: String,
+    pub username: String,
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Starts the OAuth flow, returning the URL to send the browser to and the state to put in
+/// its [`STATE_COOKIE`]
+pub async fn authorize_url() -> Result<(url::Url, String), vercel_runtime::Error> {
+    let state = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+
+    let kv = kv().await?;
+    kv.set::<(), _, _>(
+        format!("discord-state:{state}"),
+        true,
+        Some(Expiration::EX(STATE_TTL)),
+        None,
+        false,
+    )
+    .await?;
+
+    let url = url::Url::parse_with_params(
+        &format!("{}/oauth2/authorize", api_url()),
+        &[
+            ("client_id", client_id().as_str()),
+            ("redirect_uri", redirect_uri().as_str()),
+            ("response_type", "code"),
+            ("scope", "identify"),
+            ("state", state.as_str()),
+        ],
+    )?;
+
+    Ok((url, state))
+}
+
+/// Finishes the OAuth flow and asks Discord who the code belongs to. The state has to be the
+/// one this browser was given, so nobody can finish their own flow in someone else's.
+pub async fn verify(
+    req: &Request,
+    code: &str,
+    state: &str,
+) -> Result<DiscordUser, vercel_runtime::Error> {
+    if !cookie(req, STATE_COOKIE).is_some_and(|c| constant_time_eq(c.as_bytes(), state.as_bytes()))
+    {
+        return Err("Discord OAuth state doesn't match this browser"
+            .to_string()
+            .into());
+    }
+
+    let kv = kv().await?;
+    let known: Option<bool> = kv.getdel(format!("discord-state:{state}")).await?;
+    if known.is_none() {
+        return Err("Unknown or expired Discord OAuth state".to_string().into());
+    }
+
+    Provider::from_env().profile(code).await
+}
+
+/// Stores the verified Discord profile. When someone is logged in, it goes on their account,
+/// and `None` is returned if the Discord account already belongs to someone else. Otherwise
+/// it goes on the user with that Discord account, who is created on their first visit.
+pub async fn link(
+    db: &DatabaseConnection,
+    discord: DiscordUser,
+    owner_id: Option<i32>,
+) -> Result<Option<user::Model>, vercel_runtime::Error> {
+    let discord_id: i64 = discord
+        .id
+        .parse()
+        .map_err(|e| format!("Couldn't parse Discord ID! [{e}] {}", discord.id))?;
+
+    let existing: Option<user::Model> = User::find()
+        .filter(user::Column::DiscordId.eq(discord_id))
+        .one(db)
+        .await?;
+
+    let mut am = match (owner_id, existing) {
+        (Some(owner_id), Some(u)) if u.id != owner_id => return Ok(None),
+        (Some(owner_id), _) => {
+            let mut am = User::find_by_id(owner_id)
+                .one(db)
+                .await?
+                .ok_or("User not found".to_string())?
+                .into_active_model();
+            am.discord_id = ActiveValue::Set(discord_id);
+            am
+        }
+        (None, Some(u)) => u.into_active_model(),
+        (None, None) => user::ActiveModel {
+            id: ActiveValue::NotSet,
+            discord_id: ActiveValue::Set(discord_id),
+            role: ActiveValue::Set(RoleEnum::Hacker),
+            ..Default::default()
+        },
+    };
+
+    am.discord_username = ActiveValue::Set(Some(discord.username));
+    am.discord_avatar = ActiveValue::Set(discord.avatar);
+    am.discord_verified_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+
+    Ok(Some(am.save(db).await?.try_into_model()?))
+}
+
+/// Hands out a short lived token proving the holder just verified this user's Discord account
+pub async fn issue_link_token(user: &user::Model) -> Result<String, vercel_runtime::Error> {
+    let token = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+
+    let kv = kv().await?;
+    kv.set::<(), _, _>(
+        format!("discord-link:{token}"),
+        user.id,
+        Some(Expiration::EX(LINK_TTL)),
+        None,
+        false,
+    )
+    .await?;
+
+    Ok(token)
+}
+
+/// The user whose Discord account the request's link cookie vouches for
+pub async fn linked_user(req: &Request) -> Result<Option<i32>, vercel_runtime::Error> {
+    let Some(token) = cookie(req, LINK_COOKIE) else {
+        return Ok(None);
+    };
+
+    let kv = kv().await?;
+    Ok(kv.get(format!("discord-link:{token}")).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn provider(server: &MockServer) -> Provider {
+        Provider {
+            api_url: server.uri(),
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: "http://localhost/api/discord".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_code_for_profile() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/token"))
+            .and(body_string_contains("grant_type=authorization_code"))
+            .and(body_string_contains("code=good-code"))
+            .and(body_string_contains("client_secret=secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "discord-token",
+                "token_type": "Bearer",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users/@me"))
+            .and(header("authorization", "Bearer discord-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "80351110224678912",
+                "username": "nelly",
+                "avatar": "8342729096ea3675442027381ff50dfe",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let profile = provider(&server)
+            .profile("good-code")
+            .await
+            .expect("profile to be fetched");
+
+        assert_eq!(profile.id, "80351110224678912");
+        assert_eq!(profile.username, "nelly");
+        assert_eq!(
+            profile.avatar.as_deref(),
+            Some("8342729096ea3675442027381ff50dfe")
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_code_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_grant",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users/@me"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        assert!(provider(&server).profile("bad-code").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_state_from_another_browser() {
+        for cookie in [None, Some("discord_state=someone-elses")] {
+            let mut req = lambda_http::http::Request::builder();
+            if let Some(cookie) = cookie {
+                req = req.header(lambda_http::http::header::COOKIE, cookie);
+            }
+            let req = req
+                .body(vercel_runtime::Body::Empty)
+                .expect("request to build");
+
+            assert!(verify(&req, "good-code", "mine").await.is_err());
+        }
+    }
+}
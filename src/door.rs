@@ -0,0 +1,604 @@
+use std::{collections::HashMap, env};
+
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use entity::{
+    door_access, door_reader, door_rule, group_membership,
+    prelude::*,
+    sea_orm_active_enums::{DoorEffectEnum, DoorResultEnum, RoleEnum},
+    user,
+};
+use fred::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::distributions::{Alphanumeric, DistString};
+use sea_orm::{
+    prelude::*, ActiveValue, Condition, IntoActiveModel, QueryOrder, QuerySelect, Select,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use vercel_runtime::{Body, Request, Response, StatusCode};
+
+use crate::{audit, constant_time_eq, envelope, kv, param, roles};
+
+/// Header a door reader names itself with, by its slug
+pub const READER_HEADER: &str = "x-door-reader";
+/// Header with the Unix time a door reader signed its request at
+pub const TIMESTAMP_HEADER: &str = "x-door-timestamp";
+/// Header with a door reader's signature, see [`signature`]
+pub const SIGNATURE_HEADER: &str = "x-door-signature";
+
+/// How far a reader's clock may drift before its requests are turned away
+const MAX_SKEW_SECONDS: i64 = 30;
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 500;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// How a tap at a door turned out and why
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub result: DoorResultEnum,
+    pub reason: Option<String>,
+    /// The access rule that decided it, if one did
+    pub rule_id: Option<i32>,
+}
+
+impl Verdict {
+    pub fn new(result: DoorResultEnum, reason: &str) -> Self {
+        Self {
+            result,
+            reason: Some(reason.to_string()),
+            rule_id: None,
+        }
+    }
+}
+
+/// Records a tap at a door, whether or not it opened
+pub async fn log<C: ConnectionTrait>(
+    db: &C,
+    passport_id: i32,
+    owner_id: Option<i32>,
+    reader_id: Option<String>,
+    verdict: &Verdict,
+) -> Result<door_access::Model, DbErr> {
+    door_access::ActiveModel {
+        id: ActiveValue::NotSet,
+        created_at: ActiveValue::NotSet,
+        passport_id: ActiveValue::Set(passport_id),
+        owner_id: ActiveValue::Set(owner_id),
+        reader_id: ActiveValue::Set(reader_id),
+        result: ActiveValue::Set(verdict.result.clone()),
+        reason: ActiveValue::Set(verdict.reason.clone()),
+        rule_id: ActiveValue::Set(verdict.rule_id),
+    }
+    .insert(db)
+    .await
+}
+
+fn replay_key(signature: &str) -> String {
+    format!("door-signature:{signature}")
+}
+
+/// What a reader's secret is sealed for. It goes by slug, which never changes, since the
+/// secret is made before the reader has an id.
+fn secret_context(slug: &str) -> String {
+    format!("door_reader.secret:{slug}")
+}
+
+/// Makes a secret for a door reader, returning it along with the sealed form that is stored.
+/// The secret itself is only ever shown once.
+pub fn new_reader_secret(slug: &str) -> Result<(String, String), vercel_runtime::Error> {
+    let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), 48);
+    let sealed = envelope::seal(&secret, &secret_context(slug))?;
+    Ok((secret, sealed))
+}
+
+/// What a reader signs a request with: the hex HMAC-SHA256, keyed with its secret, of the
+/// timestamp, a newline and the body
+pub fn signature(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC to take any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b"\n");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+fn unauthorized(message: &str) -> Response<Body> {
+    let mut resp = Response::new(Body::Text(message.to_string()));
+    *resp.status_mut() = StatusCode::UNAUTHORIZED;
+    resp
+}
+
+/// Checks that a request was signed by a registered, enabled door reader in the last
+/// [`MAX_SKEW_SECONDS`], returning the reader or the response to send back. Each signature
+/// is only accepted once, and refusals are recorded in the audit log.
+pub async fn authenticate(
+    db: &DatabaseConnection,
+    req: &Request,
+) -> Result<Result<door_reader::Model, Response<Body>>, vercel_runtime::Error> {
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let audit = audit::Event::new(req, "door.reader");
+
+    let (Some(slug), Some(timestamp), Some(given)) = (
+        header(READER_HEADER),
+        header(TIMESTAMP_HEADER),
+        header(SIGNATURE_HEADER),
+    ) else {
+        audit.denied(db).await;
+        return Ok(Err(unauthorized("Door reader not authenticated")));
+    };
+    let audit = audit.subject("door_reader", slug);
+
+    let reader = DoorReader::find()
+        .filter(door_reader::Column::Slug.eq(slug))
+        .one(db)
+        .await?;
+    let Some(reader) = reader.filter(|r| r.disabled_at.is_none()) else {
+        audit.denied(db).await;
+        return Ok(Err(unauthorized("Unknown door reader")));
+    };
+
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|t| (Utc::now().timestamp() - t).abs() <= MAX_SKEW_SECONDS);
+    if !fresh {
+        audit.failure(db).await;
+        return Ok(Err(unauthorized("Request timestamp out of range")));
+    }
+
+    let body: &[u8] = match req.body() {
+        Body::Empty => &[],
+        Body::Text(t) => t.as_bytes(),
+        Body::Binary(b) => b,
+    };
+    let context = secret_context(&reader.slug);
+    let expected = signature(&envelope::open(&reader.secret, &context)?, timestamp, body);
+    if !constant_time_eq(expected.as_bytes(), given.to_ascii_lowercase().as_bytes()) {
+        audit.failure(db).await;
+        return Ok(Err(unauthorized("Request signature incorrect")));
+    }
+
+    // Remember the signature for as long as its timestamp could pass, so it can't be replayed
+    let kv = kv().await?;
+    let first: Option<String> = kv
+        .set(
+            replay_key(&expected),
+            1,
+            Some(Expiration::EX(MAX_SKEW_SECONDS * 2)),
+            Some(SetOptions::NX),
+            false,
+        )
+        .await?;
+    if first.is_none() {
+        audit.failure(db).await;
+        return Ok(Err(unauthorized("Request already used")));
+    }
+
+    let mut am = reader.clone().into_active_model();
+    am.last_seen_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+    if !envelope::is_current(&reader.secret)? {
+        am.secret = ActiveValue::Set(envelope::seal(
+            &envelope::open(&reader.secret, &context)?,
+            &context,
+        )?);
+    }
+    Ok(Ok(am.update(db).await?))
+}
+
+/// A door reader as shown through the API, without its secret
+pub fn reader_json(reader: &door_reader::Model) -> serde_json::Value {
+    json!({
+        "id": reader.id,
+        "slug": reader.slug,
+        "name": reader.name,
+        "created_at": reader.created_at,
+        "last_seen_at": reader.last_seen_at,
+        "disabled_at": reader.disabled_at,
+    })
+}
+
+/// The time zone door schedules are written in, `DOOR_TIMEZONE` or Purdue's if unset
+pub fn timezone() -> Tz {
+    env::var("DOOR_TIMEZONE")
+        .ok()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::America::Indiana::Indianapolis)
+}
+
+fn weekday_bit(day: Weekday) -> i16 {
+    1 << day.num_days_from_monday()
+}
+
+/// Packs days of the week into the bitmask stored on a rule, Monday being the lowest bit
+pub fn weekday_mask(days: &[Weekday]) -> i16 {
+    days.iter().fold(0, |mask, day| mask | weekday_bit(*day))
+}
+
+/// Whether a rule's date range, days and hours cover `now`. Hours that end before they
+/// start run past midnight, so 22:00 to 02:00 covers late nights, and the hours after
+/// midnight count as part of the day the window opened on.
+fn in_schedule(rule: &door_rule::Model, now: DateTime<Tz>) -> bool {
+    let today = now.date_naive();
+    let time = now.time();
+
+    let day = match (rule.start_time, rule.end_time) {
+        (Some(start), Some(end)) if start <= end => (start <= time && time < end).then_some(today),
+        (Some(start), Some(_)) if time >= start => Some(today),
+        (Some(_), Some(end)) if time < end => today.pred_opt(),
+        (Some(_), Some(_)) => None,
+        (Some(start), None) => (time >= start).then_some(today),
+        (None, Some(end)) => (time < end).then_some(today),
+        (None, None) => Some(today),
+    };
+
+    day.is_some_and(|day| {
+        rule.starts_on.is_none_or(|d| day >= d)
+            && rule.ends_on.is_none_or(|d| day <= d)
+            && rule
+                .weekdays
+                .is_none_or(|days| days & weekday_bit(day.weekday()) != 0)
+    })
+}
+
+/// The rule that decides for a user with `role` in `groups` at `now`: the matching one
+/// with the lowest priority, ties going to the oldest
+fn matching_rule<'a>(
+    rules: &'a [door_rule::Model],
+    role: &RoleEnum,
+    groups: &[i32],
+    now: DateTime<Tz>,
+) -> Option<&'a door_rule::Model> {
+    rules
+        .iter()
+        .filter(|rule| {
+            rule.role.as_ref().is_none_or(|r| r == role)
+                && rule.group_id.is_none_or(|g| groups.contains(&g))
+                && in_schedule(rule, now)
+        })
+        .min_by_key(|rule| (rule.priority, rule.id))
+}
+
+/// Why a rule decided the way it did, for the door log and the reader
+fn rule_reason(rule: &door_rule::Model) -> String {
+    match (&rule.effect, &rule.description) {
+        (DoorEffectEnum::Allow, Some(description)) => format!("Allowed: {description}"),
+        (DoorEffectEnum::Deny, Some(description)) => format!("Denied: {description}"),
+        (DoorEffectEnum::Allow, None) => format!("Allowed by rule {}", rule.id),
+        (DoorEffectEnum::Deny, None) => format!("Denied by rule {}", rule.id),
+    }
+}
+
+/// Decides whether `user` may open the door `reader_id` asked for at `now`.
+///
+/// Rules for that door and rules for every door are tried by ascending priority, and the
+/// first whose role, group and schedule all match decides. Without a matching rule, the
+/// user's role decides as it did before rules existed.
+pub async fn decide<C: ConnectionTrait>(
+    db: &C,
+    user: &user::Model,
+    reader_id: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Verdict, DbErr> {
+    let mut doors = Condition::any().add(door_rule::Column::Door.is_null());
+    if let Some(reader_id) = reader_id {
+        doors = doors.add(door_rule::Column::Door.eq(reader_id));
+    }
+    let rules = DoorRule::find().filter(doors).all(db).await?;
+
+    let groups: Vec<i32> = GroupMembership::find()
+        .filter(group_membership::Column::UserId.eq(user.id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|m| m.group_id)
+        .collect();
+
+    let now = now.with_timezone(&timezone());
+    Ok(match matching_rule(&rules, &user.role, &groups, now) {
+        Some(rule) => Verdict {
+            result: match rule.effect {
+                DoorEffectEnum::Allow => DoorResultEnum::Opened,
+                DoorEffectEnum::Deny => DoorResultEnum::NoAccess,
+            },
+            reason: Some(rule_reason(rule)),
+            rule_id: Some(rule.id),
+        },
+        None if roles::has(&user.role, roles::Permission::Door) => {
+            Verdict::new(DoorResultEnum::Opened, "Role allows door access")
+        }
+        None => Verdict::new(DoorResultEnum::NoAccess, "No door access"),
+    })
+}
+
+/// An access rule as written by an admin. Conditions left out match anything.
+#[derive(Debug, Deserialize)]
+pub struct RuleSpec {
+    /// The reader the rule is for, or every door when left out
+    pub door: Option<String>,
+    /// Lower priorities are tried first
+    #[serde(default)]
+    pub priority: i32,
+    pub effect: DoorEffectEnum,
+    pub role: Option<RoleEnum>,
+    pub group_id: Option<i32>,
+    /// Days like `mon` or `friday`
+    pub weekdays: Option<Vec<Weekday>>,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub starts_on: Option<Date>,
+    pub ends_on: Option<Date>,
+    pub description: Option<String>,
+}
+
+impl RuleSpec {
+    /// Writes the spec over every field of `am` it covers
+    pub fn apply(self, am: &mut door_rule::ActiveModel) {
+        am.door = ActiveValue::Set(self.door);
+        am.priority = ActiveValue::Set(self.priority);
+        am.effect = ActiveValue::Set(self.effect);
+        am.role = ActiveValue::Set(self.role);
+        am.group_id = ActiveValue::Set(self.group_id);
+        am.weekdays = ActiveValue::Set(self.weekdays.as_deref().map(weekday_mask));
+        am.start_time = ActiveValue::Set(self.start_time);
+        am.end_time = ActiveValue::Set(self.end_time);
+        am.starts_on = ActiveValue::Set(self.starts_on);
+        am.ends_on = ActiveValue::Set(self.ends_on);
+        am.description = ActiveValue::Set(self.description);
+    }
+}
+
+/// A rule as shown through the API, with its days spelled out
+pub fn rule_json(rule: &door_rule::Model) -> serde_json::Value {
+    let weekdays: Option<Vec<String>> = rule.weekdays.map(|mask| {
+        WEEKDAYS
+            .iter()
+            .filter(|day| mask & weekday_bit(**day) != 0)
+            .map(|day| day.to_string())
+            .collect()
+    });
+
+    json!({
+        "id": rule.id,
+        "door": rule.door,
+        "priority": rule.priority,
+        "effect": rule.effect,
+        "role": rule.role,
+        "group_id": rule.group_id,
+        "weekdays": weekdays,
+        "start_time": rule.start_time,
+        "end_time": rule.end_time,
+        "starts_on": rule.starts_on,
+        "ends_on": rule.ends_on,
+        "description": rule.description,
+        "created_at": rule.created_at,
+    })
+}
+
+/// Narrows `query` down by the `since` and `until` (RFC 3339) and `cursor` parameters, and
+/// returns how many entries a page should have from `limit`
+pub fn window(
+    mut query: Select<DoorAccess>,
+    params: &HashMap<String, String>,
+) -> Result<(Select<DoorAccess>, u64), vercel_runtime::Error> {
+    if let Some(since) = params.get("since") {
+        let since = DateTime::parse_from_rfc3339(since)?;
+        query = query.filter(door_access::Column::CreatedAt.gte(since));
+    }
+    if let Some(until) = params.get("until") {
+        let until = DateTime::parse_from_rfc3339(until)?;
+        query = query.filter(door_access::Column::CreatedAt.lt(until));
+    }
+    if let Some(cursor) = param::<i32>(params, "cursor")? {
+        query = query.filter(door_access::Column::Id.lt(cursor));
+    }
+
+    let limit = param::<u64>(params, "limit")?.unwrap_or(DEFAULT_LIMIT);
+
+    Ok((query, limit.clamp(1, MAX_LIMIT)))
+}
+
+/// Fetches a page of entries, newest first, along with the cursor for the next page
+pub async fn page<C: ConnectionTrait>(
+    db: &C,
+    query: Select<DoorAccess>,
+    limit: u64,
+) -> Result<(Vec<door_access::Model>, Option<String>), DbErr> {
+    let mut entries = query
+        .order_by_desc(door_access::Column::Id)
+        .limit(limit + 1)
+        .all(db)
+        .await?;
+
+    let next_cursor = if entries.len() as u64 > limit {
+        entries.truncate(limit as usize);
+        entries.last().map(|e| e.id.to_string())
+    } else {
+        None
+    };
+
+    Ok((entries, next_cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone};
+
+    use super::*;
+
+    fn rule(id: i32) -> door_rule::Model {
+        door_rule::Model {
+            id,
+            door: None,
+            priority: 0,
+            effect: DoorEffectEnum::Allow,
+            role: None,
+            group_id: None,
+            weekdays: None,
+            start_time: None,
+            end_time: None,
+            starts_on: None,
+            ends_on: None,
+            description: None,
+            created_at: Utc::now().fixed_offset(),
+        }
+    }
+
+    fn hm(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).expect("time to be valid")
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, day).expect("date to be valid")
+    }
+
+    /// October 16th 2026 is a Friday
+    fn at(day: u32, hour: u32, min: u32) -> DateTime<Tz> {
+        Tz::UTC
+            .with_ymd_and_hms(2026, 10, day, hour, min, 0)
+            .single()
+            .expect("time to exist")
+    }
+
+    fn late_nights() -> door_rule::Model {
+        door_rule::Model {
+            start_time: Some(hm(22, 0)),
+            end_time: Some(hm(2, 0)),
+            ..rule(1)
+        }
+    }
+
+    #[test]
+    fn hours_wrap_past_midnight() {
+        let rule = late_nights();
+
+        assert!(in_schedule(&rule, at(16, 23, 0)));
+        assert!(in_schedule(&rule, at(17, 1, 59)));
+        assert!(!in_schedule(&rule, at(17, 2, 0)));
+        assert!(!in_schedule(&rule, at(16, 21, 59)));
+    }
+
+    #[test]
+    fn weekdays_are_checked() {
+        let rule = door_rule::Model {
+            weekdays: Some(weekday_mask(&[Weekday::Mon, Weekday::Fri])),
+            ..rule(1)
+        };
+
+        assert!(in_schedule(&rule, at(16, 12, 0)));
+        assert!(!in_schedule(&rule, at(17, 12, 0)));
+        assert!(in_schedule(&rule, at(19, 12, 0)));
+    }
+
+    #[test]
+    fn after_midnight_counts_as_the_day_before() {
+        let rule = door_rule::Model {
+            weekdays: Some(weekday_mask(&[Weekday::Fri])),
+            ..late_nights()
+        };
+
+        assert!(in_schedule(&rule, at(16, 23, 0)));
+        assert!(in_schedule(&rule, at(17, 1, 0)));
+        assert!(!in_schedule(&rule, at(16, 1, 0)));
+        assert!(!in_schedule(&rule, at(17, 23, 0)));
+    }
+
+    #[test]
+    fn date_range_is_inclusive() {
+        let rule = door_rule::Model {
+            starts_on: Some(date(16)),
+            ends_on: Some(date(17)),
+            ..rule(1)
+        };
+
+        assert!(!in_schedule(&rule, at(15, 23, 59)));
+        assert!(in_schedule(&rule, at(16, 0, 0)));
+        assert!(in_schedule(&rule, at(17, 23, 59)));
+        assert!(!in_schedule(&rule, at(18, 0, 0)));
+    }
+
+    #[test]
+    fn date_range_follows_the_night_it_started() {
+        let rule = door_rule::Model {
+            ends_on: Some(date(16)),
+            ..late_nights()
+        };
+
+        assert!(in_schedule(&rule, at(17, 1, 0)));
+        assert!(!in_schedule(&rule, at(17, 23, 0)));
+
+        let rule = door_rule::Model {
+            starts_on: Some(date(17)),
+            ..late_nights()
+        };
+
+        assert!(!in_schedule(&rule, at(17, 1, 0)));
+        assert!(in_schedule(&rule, at(17, 23, 0)));
+    }
+
+    #[test]
+    fn lowest_priority_decides() {
+        let rules = [
+            door_rule::Model {
+                priority: 10,
+                ..rule(1)
+            },
+            door_rule::Model {
+                priority: 5,
+                effect: DoorEffectEnum::Deny,
+                ..rule(2)
+            },
+            door_rule::Model {
+                priority: 5,
+                ..rule(3)
+            },
+            door_rule::Model {
+                priority: 0,
+                role: Some(RoleEnum::Admin),
+                ..rule(4)
+            },
+        ];
+
+        let matching = matching_rule(&rules, &RoleEnum::Hacker, &[], at(16, 12, 0));
+        assert_eq!(matching.map(|r| r.id), Some(2));
+
+        let matching = matching_rule(&rules, &RoleEnum::Admin, &[], at(16, 12, 0));
+        assert_eq!(matching.map(|r| r.id), Some(4));
+    }
+
+    #[test]
+    fn rules_out_of_schedule_or_group_are_skipped() {
+        let rules = [
+            door_rule::Model {
+                priority: 0,
+                group_id: Some(7),
+                ..rule(1)
+            },
+            door_rule::Model {
+                priority: 1,
+                weekdays: Some(weekday_mask(&[Weekday::Sat])),
+                ..rule(2)
+            },
+            door_rule::Model {
+                priority: 2,
+                ..rule(3)
+            },
+        ];
+
+        let matching = matching_rule(&rules, &RoleEnum::Hacker, &[], at(16, 12, 0));
+        assert_eq!(matching.map(|r| r.id), Some(3));
+
+        let matching = matching_rule(&rules, &RoleEnum::Hacker, &[7], at(16, 12, 0));
+        assert_eq!(matching.map(|r| r.id), Some(1));
+    }
+}
@@ -0,0 +1,179 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::prelude::*;
+use std::env;
+
+const PREFIX: &str = "enc:v";
+const NONCE_LEN: usize = 12;
+
+/// Every key encryption key by version, from `TFA_ENCRYPTION_KEYS` as comma separated
+/// `version:base64key` pairs
+fn keys() -> Result<Vec<(u32, Key<Aes256Gcm>)>, vercel_runtime::Error> {
+    let var = env::var("TFA_ENCRYPTION_KEYS")
+        .map_err(|_| "TFA_ENCRYPTION_KEYS is not set".to_string())?;
+
+    var.split(',')
+        .map(|pair| {
+            let (version, key) = pair
+                .trim()
+                .split_once(':')
+                .ok_or("Encryption keys must be version:key pairs".to_string())?;
+            let key = BASE64_STANDARD.decode(key)?;
+            if key.len() != 32 {
+                return Err("Encryption keys must be 32 bytes".to_string().into());
+            }
+
+            Ok((version.parse()?, *Key::<Aes256Gcm>::from_slice(&key)))
+        })
+        .collect()
+}
+
+fn encrypt(
+    key: &Key<Aes256Gcm>,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, vercel_runtime::Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| "Encryption failed".to_string())?;
+
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt(
+    key: &Key<Aes256Gcm>,
+    sealed: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, vercel_runtime::Error> {
+    if sealed.len() < NONCE_LEN {
+        return Err("Sealed value is too short".to_string().into());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    Ok(Aes256Gcm::new(key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| "Decryption failed".to_string())?)
+}
+
+/// The version a stored value was sealed with, or `None` if it isn't sealed
+pub fn version(stored: &str) -> Option<u32> {
+    stored.strip_prefix(PREFIX)?.split(':').next()?.parse().ok()
+}
+
+/// Whether the value is sealed with the newest key
+pub fn is_current(stored: &str) -> Result<bool, vercel_runtime::Error> {
+    let newest = keys()?.into_iter().map(|(v, _)| v).max();
+    Ok(version(stored).is_some() && version(stored) == newest)
+}
+
+/// Encrypts a secret for storage with its own data key, which is in turn encrypted with the
+/// newest key. The result looks like `enc:v<version>:<wrapped data key>:<ciphertext>`, each
+/// part being a nonce followed by the AES-GCM output. `context` says where the value is kept,
+/// such as `user.totp:<id>`, and is bound as associated data so the value can't be opened
+/// anywhere else.
+pub fn seal(plaintext: &str, context: &str) -> Result<String, vercel_runtime::Error> {
+    let (version, kek) = keys()?
+        .into_iter()
+        .max_by_key(|(v, _)| *v)
+        .ok_or("No encryption keys are configured".to_string())?;
+
+    let dek = Aes256Gcm::generate_key(OsRng);
+    let wrapped = encrypt(&kek, &dek, context.as_bytes())?;
+    let ciphertext = encrypt(&dek, plaintext.as_bytes(), context.as_bytes())?;
+
+    Ok(format!(
+        "{PREFIX}{version}:{}:{}",
+        BASE64_STANDARD.encode(wrapped),
+        BASE64_STANDARD.encode(ciphertext)
+    ))
+}
+
+/// Decrypts a stored secret with whichever key version sealed it, failing if it was sealed
+/// for another `context`. Every secret has been sealed since the migration that encrypted
+/// them, so a value that isn't is refused.
+pub fn open(stored: &str, context: &str) -> Result<String, vercel_runtime::Error> {
+    let Some(rest) = stored.strip_prefix(PREFIX) else {
+        return Err("Value isn't sealed".to_string().into());
+    };
+
+    let mut parts = rest.splitn(3, ':');
+    let (Some(version), Some(wrapped), Some(ciphertext)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err("Malformed sealed value".to_string().into());
+    };
+    let version: u32 = version.parse()?;
+
+    let (_, kek) = keys()?
+        .into_iter()
+        .find(|(v, _)| *v == version)
+        .ok_or(format!(
+            "Encryption key version {version} is not configured"
+        ))?;
+
+    let dek = decrypt(&kek, &BASE64_STANDARD.decode(wrapped)?, context.as_bytes())?;
+    if dek.len() != 32 {
+        return Err("Wrapped key is the wrong length".to_string().into());
+    }
+    let plaintext = decrypt(
+        Key::<Aes256Gcm>::from_slice(&dek),
+        &BASE64_STANDARD.decode(ciphertext)?,
+        context.as_bytes(),
+    )?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configure() {
+        env::set_var(
+            "TFA_ENCRYPTION_KEYS",
+            format!("1:{}", BASE64_STANDARD.encode([7u8; 32])),
+        );
+    }
+
+    #[test]
+    fn opens_what_it_sealed() {
+        configure();
+        let sealed = seal("secret", "user.totp:1").expect("value to seal");
+
+        assert_eq!(version(&sealed), Some(1));
+        assert_eq!(
+            open(&sealed, "user.totp:1").expect("value to open"),
+            "secret"
+        );
+    }
+
+    #[test]
+    fn refuses_another_context() {
+        configure();
+        let sealed = seal("secret", "user.totp:1").expect("value to seal");
+
+        assert!(open(&sealed, "user.totp:2").is_err());
+    }
+
+    #[test]
+    fn refuses_plaintext() {
+        configure();
+
+        assert!(open("JBSWY3DPEHPK3PXP", "user.totp:1").is_err());
+    }
+}
@@ -0,0 +1,18 @@
+use entity::{group_membership, prelude::*, user_group};
+use sea_orm::{prelude::*, QueryOrder};
+
+/// Scope that lets a client see which groups a user is in
+pub const SCOPE: &str = "groups";
+
+/// Slugs of every group the user belongs to
+pub async fn slugs<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<Vec<String>, DbErr> {
+    Ok(UserGroup::find()
+        .inner_join(GroupMembership)
+        .filter(group_membership::Column::UserId.eq(user_id))
+        .order_by_asc(user_group::Column::Slug)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|g| g.slug)
+        .collect())
+}
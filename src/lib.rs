@@ -1,26 +1,28 @@
 #![deny(clippy::unwrap_used)]
 
+use base64::prelude::*;
 use core::ops::Deref;
 use fred::prelude::*;
 use jsonwebkey::JsonWebKey;
 use jsonwebtoken::{decode, encode, Header, TokenData, Validation};
 use lambda_http::http::{
-    header::{CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE},
+    header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION, WWW_AUTHENTICATE},
     HeaderValue,
 };
 use sea_orm::Database;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, env, fmt::Display, ops::DerefMut, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, env, fmt::Display, ops::DerefMut, str::FromStr};
 use vercel_runtime::{Body, Request, Response, StatusCode};
 
 use chrono::{DateTime, Months, Utc};
 use entity::prelude::*;
-use entity::{auth_grant, auth_token};
+use entity::{auth_grant, auth_session, auth_token, passport};
 use oxide_auth::{
+    code_grant::accesstoken::Request as TokenRequest,
     endpoint::ResponseStatus,
     frontends::{self, simple::endpoint::Vacant},
     primitives::{
-        grant::Grant,
+        grant::{Extensions, Grant, GrantExtension, Value},
         issuer::{IssuedToken, TokenType},
     },
 };
@@ -31,7 +33,8 @@ use oxide_auth::{
 };
 use oxide_auth_async::primitives::{Authorizer, Issuer};
 use oxide_auth_async::{
-    endpoint::resource::ResourceFlow, endpoint::Endpoint, endpoint::OwnerSolicitor,
+    endpoint::resource::ResourceFlow, endpoint::AccessTokenExtension, endpoint::Endpoint,
+    endpoint::Extension, endpoint::OwnerSolicitor,
 };
 use rand::distributions::{Alphanumeric, DistString};
 use sea_orm::{prelude::*, ActiveValue};
@@ -39,12 +42,27 @@ use sea_orm::{Condition, IntoActiveModel};
 
 use thiserror::Error;
 
+pub mod audit;
+pub mod ceremony;
+pub mod discord;
+pub mod door;
+pub mod envelope;
+pub mod groups;
+pub mod lifecycle;
+pub mod roles;
+pub mod step_up;
 pub mod tfa;
+pub mod webauthn;
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Invalid body type")]
     InvalidBodyType,
+    #[error("Passport cannot go from {from:?} to {to:?}")]
+    InvalidTransition {
+        from: entity::sea_orm_active_enums::PassportStatusEnum,
+        to: entity::sea_orm_active_enums::PassportStatusEnum,
+    },
 }
 
 #[derive(Debug, Default)]
@@ -149,7 +167,7 @@ impl From<RequestCompat> for Request {
 impl WebRequest for RequestCompat {
     type Error = vercel_runtime::Error;
     type Response = ResponseCompat;
-    fn authheader(&mut self) -> Result<Option<std::borrow::Cow<str>>, Self::Error> {
+    fn authheader(&mut self) -> Result<Option<std::borrow::Cow<'_, str>>, Self::Error> {
         Ok(self.headers().iter().find_map(|(k, v)| {
             if k == "Authorization" {
                 Some(Cow::Borrowed(v.to_str().expect("head to be valid string")))
@@ -161,7 +179,7 @@ impl WebRequest for RequestCompat {
 
     fn urlbody(
         &mut self,
-    ) -> Result<std::borrow::Cow<dyn oxide_auth::endpoint::QueryParameter + 'static>, Self::Error>
+    ) -> Result<std::borrow::Cow<'_, dyn oxide_auth::endpoint::QueryParameter + 'static>, Self::Error>
     {
         let body: &Body = self.body();
 
@@ -190,7 +208,7 @@ impl WebRequest for RequestCompat {
 
     fn query(
         &mut self,
-    ) -> Result<std::borrow::Cow<dyn oxide_auth::endpoint::QueryParameter + 'static>, Self::Error>
+    ) -> Result<std::borrow::Cow<'_, dyn oxide_auth::endpoint::QueryParameter + 'static>, Self::Error>
     {
         let url = url::Url::parse(&self.uri().to_string())?;
 
@@ -210,41 +228,48 @@ pub struct ClientData<'a> {
     pub scope: &'a str,
 }
 
+/// Scope that lets a client see a user's role and whether they have TOTP set up
+pub const PROFILE_SCOPE: &str = "profile";
+/// Scope that lets a client see a user's latest passport
+pub const PASSPORT_SCOPE: &str = "passport";
+/// Scope that lets a client see a user's linked Discord account
+pub const DISCORD_SCOPE: &str = "discord";
+
 pub const VALID_CLIENTS: [ClientData<'static>; 7] = [
     ClientData {
         client_id: "dashboard",
         url: "https://dash.purduehackers.com/api/callback",
-        scope: "user:read",
+        scope: "user:read profile passport discord groups",
     },
     ClientData {
         client_id: "passports",
         url: "https://passports.purduehackers.com/callback",
-        scope: "user:read user",
+        scope: "user:read user profile passport discord",
     },
     ClientData {
         client_id: "authority",
         url: "authority://callback",
-        scope: "admin:read admin",
+        scope: "admin:read admin staff",
     },
     ClientData {
         client_id: "auth-test",
         url: "https://id-auth.purduehackers.com/api/auth/callback/purduehackers-id",
-        scope: "user:read",
+        scope: "user:read profile discord",
     },
     ClientData {
         client_id: "vulcan-auth",
         url: "https://auth.purduehackers.com/source/oauth/callback/purduehackers-id/",
-        scope: "user:read",
+        scope: "user:read profile discord groups",
     },
     ClientData {
         client_id: "shad-moe",
         url: "https://auth.shad.moe/source/oauth/callback/purduehackers-id/",
-        scope: "user:read",
+        scope: "user:read profile discord",
     },
     ClientData {
         client_id: "shquid",
         url: "https://www.imsqu.id/auth/callback/purduehackers-id",
-        scope: "user:read",
+        scope: "user:read profile discord",
     },
 ];
 
@@ -267,6 +292,38 @@ pub fn client_registry() -> ClientMap {
     clients
 }
 
+/// A backend service that authenticates with a shared secret instead of a user's token
+pub struct ConfidentialClient<'a> {
+    pub client_id: &'a str,
+    /// Environment variable holding the client's secret
+    pub secret_var: &'a str,
+}
+
+pub const CONFIDENTIAL_CLIENTS: [ConfidentialClient<'static>; 1] = [ConfidentialClient {
+    client_id: "passport-issuing-office",
+    secret_var: "ISSUING_OFFICE_SECRET",
+}];
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Checks the request's `Authorization: Basic` header against the confidential clients and
+/// returns whichever one it belongs to
+pub fn confidential_client(req: &Request) -> Option<&'static str> {
+    let auth = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let auth = BASE64_STANDARD.decode(auth.strip_prefix("Basic ")?).ok()?;
+    let auth = String::from_utf8(auth).ok()?;
+    let (client_id, secret) = auth.split_once(':')?;
+
+    let client = CONFIDENTIAL_CLIENTS
+        .iter()
+        .find(|c| c.client_id == client_id)?;
+    let expected = env::var(client.secret_var).ok().filter(|s| !s.is_empty())?;
+
+    constant_time_eq(secret.as_bytes(), expected.as_bytes()).then_some(client.client_id)
+}
+
 #[derive(Serialize)]
 pub struct APIError<'a> {
     pub message: &'a str,
@@ -294,6 +351,75 @@ pub async fn db() -> Result<DatabaseConnection, vercel_runtime::Error> {
     Ok(db)
 }
 
+/// Finds a cookie by name, if the browser sent it
+pub fn cookie<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    for cookie in req.headers().get_all(COOKIE) {
+        for itm in cookie.to_str().unwrap_or_default().split("; ") {
+            if let Some((k, v)) = itm.split_once('=') {
+                if k == name {
+                    return Some(v);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Parses the query parameter `name`, if it was given
+pub fn param<T: FromStr>(
+    params: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, vercel_runtime::Error>
+where
+    T::Err: Display,
+{
+    Ok(params
+        .get(name)
+        .map(|v| v.parse())
+        .transpose()
+        .map_err(|e| format!("Failed to parse {name}! {e}"))?)
+}
+
+/// Finds the `session` cookie, if the browser sent one
+pub fn session_token(req: &Request) -> Option<&str> {
+    cookie(req, "session")
+}
+
+/// Looks up the unexpired session belonging to the request's `session` cookie
+pub async fn session(
+    db: &DatabaseConnection,
+    req: &Request,
+) -> Result<Option<auth_session::Model>, vercel_runtime::Error> {
+    let Some(token) = session_token(req) else {
+        return Ok(None);
+    };
+
+    Ok(AuthSession::find()
+        .filter(
+            Condition::all()
+                .add(auth_session::Column::Token.eq(token))
+                .add(auth_session::Column::Until.gte(Utc::now())),
+        )
+        .one(db)
+        .await?)
+}
+
+/// Posts a message to the admin webhook, if there is one. Whatever it's about is in the
+/// audit log either way, so a webhook that's down doesn't fail the request.
+pub async fn notify_admins(message: &str) {
+    let Ok(url) = env::var("ADMIN_WEBHOOK_URL") else {
+        return;
+    };
+
+    let _ = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "content": message }))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+}
+
 /// Vercel makes me do this
 pub fn map_error_to_readable<E: Display>(r: Result<Response<Body>, E>) -> Response<Body> {
     match r {
@@ -314,6 +440,40 @@ pub struct PassportRecord {
     pub secret: String,
 }
 
+/// A passport as shown through the API, without its secret
+#[derive(Debug, Serialize)]
+pub struct PassportSummary {
+    pub id: i32,
+    pub owner_id: i32,
+    pub version: i32,
+    pub name: String,
+    pub surname: String,
+    pub date_of_birth: ChronoDate,
+    pub date_of_issue: ChronoDate,
+    pub place_of_origin: String,
+    pub ceremony_id: Option<i32>,
+    pub status: entity::sea_orm_active_enums::PassportStatusEnum,
+    pub status_changed_at: DateTimeWithTimeZone,
+}
+
+impl From<passport::Model> for PassportSummary {
+    fn from(p: passport::Model) -> Self {
+        Self {
+            id: p.id,
+            owner_id: p.owner_id,
+            version: p.version,
+            name: p.name,
+            surname: p.surname,
+            date_of_birth: p.date_of_birth,
+            date_of_issue: p.date_of_issue,
+            place_of_origin: p.place_of_origin,
+            ceremony_id: p.ceremony_id,
+            status: p.status,
+            status_changed_at: p.status_changed_at,
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! wrap_error {
     ($fn:ident) => {
@@ -335,6 +495,28 @@ impl Issuer for JwtIssuer {
         grant: oxide_auth::primitives::grant::Grant,
     ) -> Result<oxide_auth::primitives::prelude::IssuedToken, ()> {
         let until = Utc::now() + Months::new(1);
+        let grant_id = grant
+            .extensions
+            .private()
+            .find(|(k, _)| *k == GrantIdExtension.identifier())
+            .and_then(|(_, v)| v.and_then(|v| v.parse().ok()));
+        let groups = if grant.scope.iter().any(|s| s == groups::SCOPE) {
+            let db = db().await.expect("db to be accessible");
+            let user_id: i32 = grant.owner_id.parse().expect("db id to be i32");
+            Some(groups::slugs(&db, user_id).await.expect("db op to succeed"))
+        } else {
+            None
+        };
+        let authentication = match grant_id {
+            Some(grant_id) => {
+                let db = db().await.expect("db to be accessible");
+                AuthGrant::find_by_id(grant_id)
+                    .one(&db)
+                    .await
+                    .expect("db op to succeed")
+            }
+            None => None,
+        };
         let claims = Claims {
             sub: grant.owner_id,
             exp: until.timestamp(),
@@ -342,11 +524,20 @@ impl Issuer for JwtIssuer {
             iss: "id".to_string(),
             aud: grant.client_id,
             scope: grant.scope,
+            grant: grant_id,
+            groups,
+            auth_time: authentication
+                .as_ref()
+                .and_then(|g| g.auth_time)
+                .map(|t| t.timestamp()),
+            amr: authentication
+                .and_then(|g| g.amr)
+                .and_then(|amr| serde_json::from_value(amr).ok()),
         };
 
         let jwk = get_jwk();
         let token = encode(
-            &Header::new(jwk.algorithm.unwrap().into()),
+            &Header::new(jwk.algorithm.expect("algo").into()),
             &claims,
             &jwk.key.to_encoding_key(),
         )
@@ -381,6 +572,19 @@ impl Issuer for JwtIssuer {
             return Err(());
         };
 
+        // Tokens are revoked by deleting the grant they were issued from
+        if let Some(grant_id) = claims.grant {
+            let db = db().await.expect("db to be accessible");
+            if AuthGrant::find_by_id(grant_id)
+                .one(&db)
+                .await
+                .expect("db op to succeed")
+                .is_none()
+            {
+                return Ok(None);
+            }
+        }
+
         let Some(redirect_uri) = VALID_CLIENTS
             .iter()
             .find(|c| c.client_id == claims.aud)
@@ -515,6 +719,14 @@ struct Claims {
     iss: String, // Issuer
     aud: String, // Audience
     scope: Scope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grant: Option<i32>, // Backing auth_grant row, so the token can be revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<String>>, // Group slugs, with the groups scope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth_time: Option<i64>, // When the user last tapped their passport (timestamp)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amr: Option<Vec<String>>, // How they authenticated then
 }
 
 /// Not currently in use but can be switched to whenever
@@ -565,11 +777,15 @@ impl Authorizer for JwtAuthorizer {
             iss: "id-grant".to_string(),
             aud: grant.client_id,
             scope: grant.scope,
+            grant: None,
+            groups: None,
+            auth_time: None,
+            amr: None,
         };
 
         let jwk = get_jwk();
         let token = encode(
-            &Header::new(jwk.algorithm.unwrap().into()),
+            &Header::new(jwk.algorithm.expect("algo").into()),
             &claims,
             &jwk.key.to_encoding_key(),
         )
@@ -635,6 +851,9 @@ impl Authorizer for DbAuthorizer {
             code: ActiveValue::Set(Some(
                 Alphanumeric.sample_string(&mut rand::thread_rng(), 32),
             )),
+            passport_id: ActiveValue::NotSet,
+            auth_time: ActiveValue::NotSet,
+            amr: ActiveValue::NotSet,
         };
 
         let grant = model.insert(&db).await.expect("insert to work");
@@ -663,9 +882,11 @@ impl Authorizer for DbAuthorizer {
                     serde_json::from_value(g.scope).expect("scope to be deserializable");
                 let uri: String = serde_json::from_value(g.redirect_uri)
                     .expect("redirect uri to be deserializable");
+                let mut extensions = Extensions::new();
+                extensions.set(&GrantIdExtension, Value::private(Some(g.id.to_string())));
                 Some(oxide_auth::primitives::grant::Grant {
                     client_id: g.client_id,
-                    extensions: Default::default(),
+                    extensions,
                     owner_id: g.owner_id.to_string(),
                     scope: Scope::from_str(&scope).expect("scope deserialization from string"),
                     redirect_uri: Url::from_str(&uri).expect("url deserialization from string"),
@@ -677,12 +898,42 @@ impl Authorizer for DbAuthorizer {
     }
 }
 
+/// Carries the id of the `auth_grant` row from the authorization code to the issued token
+struct GrantIdExtension;
+
+impl GrantExtension for GrantIdExtension {
+    fn identifier(&self) -> &'static str {
+        "grant_id"
+    }
+}
+
+/// Hands the extensions attached to an authorization code on to the token issuer unchanged
+pub struct GrantPassthrough;
+
+impl Extension for GrantPassthrough {
+    fn access_token(&mut self) -> Option<&mut (dyn AccessTokenExtension + Send)> {
+        Some(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl AccessTokenExtension for GrantPassthrough {
+    async fn extend(
+        &mut self,
+        _: &(dyn TokenRequest + Sync),
+        data: Extensions,
+    ) -> Result<Extensions, ()> {
+        Ok(data)
+    }
+}
+
 pub struct OAuthEndpoint<T: OwnerSolicitor<RequestCompat>> {
     solicitor: T,
     scopes: Vec<Scope>,
     registry: ClientMap,
     issuer: JwtIssuer,
     authorizer: DbAuthorizer,
+    extension: GrantPassthrough,
 }
 
 impl<T: OwnerSolicitor<RequestCompat>> OAuthEndpoint<T> {
@@ -693,6 +944,7 @@ impl<T: OwnerSolicitor<RequestCompat>> OAuthEndpoint<T> {
             registry: client_registry(),
             issuer: JwtIssuer,
             authorizer: DbAuthorizer,
+            extension: GrantPassthrough,
         }
     }
 }
@@ -751,14 +1003,29 @@ impl<T: OwnerSolicitor<RequestCompat> + Send> Endpoint<RequestCompat> for OAuthE
     ) -> Option<&mut (dyn oxide_auth_async::primitives::Authorizer + Send)> {
         Some(&mut self.authorizer)
     }
+
+    fn extension(&mut self) -> Option<&mut (dyn Extension + Send)> {
+        Some(&mut self.extension)
+    }
 }
 
 pub async fn oauth_user(req: Request, scopes: Vec<Scope>) -> Result<i32, vercel_runtime::Error> {
-    let user = ResourceFlow::prepare(OAuthEndpoint::new(Vacant, scopes))
+    Ok(oauth_grant(req, scopes).await?.0)
+}
+
+/// Like [`oauth_user`], but also returns every scope the token was granted
+pub async fn oauth_grant(
+    req: Request,
+    scopes: Vec<Scope>,
+) -> Result<(i32, Scope), vercel_runtime::Error> {
+    let grant = ResourceFlow::prepare(OAuthEndpoint::new(Vacant, scopes))
         .map_err(|e| format!("Resource flow prep error: {e:?}"))?
         .execute(RequestCompat(req))
         .await
         .map_err(|e| format!("Resource flow exec error: {e:?}"))?;
 
-    Ok(user.owner_id.parse().expect("db id to be i32"))
+    Ok((
+        grant.owner_id.parse().expect("db id to be i32"),
+        grant.scope,
+    ))
 }
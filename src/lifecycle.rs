@@ -0,0 +1,213 @@
+use std::env;
+
+use chrono::{Months, Utc};
+use entity::{
+    auth_grant, auth_session, passport, passport_status_change, prelude::*,
+    sea_orm_active_enums::PassportStatusEnum,
+};
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel, TransactionTrait};
+
+use crate::Error;
+
+use PassportStatusEnum::*;
+
+/// Whether a passport in `from` may be moved to `to`
+pub fn can_transition(from: &PassportStatusEnum, to: &PassportStatusEnum) -> bool {
+    matches!(
+        (from, to),
+        (Draft, PendingCeremony)
+            | (PendingCeremony, Draft)
+            | (PendingCeremony, Issued)
+            | (Issued, Active)
+            | (Active, Suspended)
+            | (Suspended, Active)
+            | (Active | Suspended, Lost)
+            | (Active | Suspended, Expired)
+            | (
+                Draft | PendingCeremony | Issued | Active | Suspended | Lost,
+                Revoked
+            )
+    )
+}
+
+/// Whether the passport data can still be edited in place instead of issuing a new one
+pub fn is_editable(status: &PassportStatusEnum) -> bool {
+    matches!(status, Draft | PendingCeremony)
+}
+
+/// Why a passport in this state can't be used to open doors or log in, if it can't
+pub fn refusal(status: &PassportStatusEnum) -> Option<&'static str> {
+    match status {
+        Active => None,
+        Draft | PendingCeremony | Issued => Some("Passport not activated"),
+        Suspended => Some("Passport suspended"),
+        Lost => Some("Passport reported lost"),
+        Revoked => Some("Passport revoked"),
+        Expired => Some("Passport expired"),
+    }
+}
+
+/// How long a passport is valid for after its date of issue, four years unless
+/// `PASSPORT_VALIDITY_MONTHS` says otherwise
+pub fn validity() -> Months {
+    Months::new(
+        env::var("PASSPORT_VALIDITY_MONTHS")
+            .ok()
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(48),
+    )
+}
+
+/// The first day the passport is no longer valid
+pub fn expires_on(passport: &passport::Model) -> ChronoDate {
+    passport
+        .date_of_issue
+        .checked_add_months(validity())
+        .unwrap_or(ChronoDate::MAX)
+}
+
+/// Marks the passport as expired if its validity period has run out
+pub async fn expire_if_due<C: ConnectionTrait>(
+    db: &C,
+    passport: passport::Model,
+) -> Result<passport::Model, vercel_runtime::Error> {
+    if !matches!(passport.status, Active | Suspended)
+        || expires_on(&passport) > Utc::now().date_naive()
+    {
+        return Ok(passport);
+    }
+
+    transition(
+        db,
+        passport,
+        Expired,
+        Some("validity period ended".to_string()),
+        None,
+    )
+    .await
+}
+
+/// Records the status a freshly created passport starts in
+pub async fn record_initial<C: ConnectionTrait>(
+    db: &C,
+    passport: &passport::Model,
+    actor: Option<i32>,
+) -> Result<(), vercel_runtime::Error> {
+    passport_status_change::ActiveModel {
+        id: ActiveValue::NotSet,
+        passport_id: ActiveValue::Set(passport.id),
+        from_status: ActiveValue::Set(None),
+        to_status: ActiveValue::Set(passport.status.clone()),
+        reason: ActiveValue::Set(Some("created".to_string())),
+        changed_by: ActiveValue::Set(actor),
+        changed_at: ActiveValue::Set(passport.status_changed_at),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether moving a passport to this state cuts off the logins it was used for
+fn revokes_access(status: &PassportStatusEnum) -> bool {
+    matches!(status, Lost | Suspended | Revoked)
+}
+
+/// Moves a passport to a new status, recording who did it and why. Moving it to the status
+/// it's already in changes nothing. Lost, suspended and revoked passports also lose every
+/// session and grant obtained by tapping them.
+pub async fn transition<C: ConnectionTrait>(
+    db: &C,
+    passport: passport::Model,
+    to: PassportStatusEnum,
+    reason: Option<String>,
+    actor: Option<i32>,
+) -> Result<passport::Model, vercel_runtime::Error> {
+    if passport.status == to {
+        return Ok(passport);
+    }
+    if !can_transition(&passport.status, &to) {
+        return Err(Box::new(Error::InvalidTransition {
+            from: passport.status,
+            to,
+        }));
+    }
+
+    let now = Utc::now().fixed_offset();
+
+    passport_status_change::ActiveModel {
+        id: ActiveValue::NotSet,
+        passport_id: ActiveValue::Set(passport.id),
+        from_status: ActiveValue::Set(Some(passport.status.clone())),
+        to_status: ActiveValue::Set(to.clone()),
+        reason: ActiveValue::Set(reason),
+        changed_by: ActiveValue::Set(actor),
+        changed_at: ActiveValue::Set(now),
+    }
+    .insert(db)
+    .await?;
+
+    if revokes_access(&to) {
+        // Deleting the grants also invalidates every token issued from them
+        AuthSession::delete_many()
+            .filter(auth_session::Column::PassportId.eq(passport.id))
+            .exec(db)
+            .await?;
+        AuthGrant::delete_many()
+            .filter(auth_grant::Column::PassportId.eq(passport.id))
+            .exec(db)
+            .await?;
+    }
+
+    let mut am = passport.into_active_model();
+    am.status = ActiveValue::Set(to);
+    am.status_changed_at = ActiveValue::Set(now);
+
+    Ok(am.update(db).await?)
+}
+
+/// Activates a passport, walking it through issuance if needed, and revokes whichever
+/// passport it replaces
+pub async fn activate(
+    db: &DatabaseConnection,
+    passport: passport::Model,
+    reason: Option<String>,
+    actor: Option<i32>,
+) -> Result<passport::Model, vercel_runtime::Error> {
+    let txn = db.begin().await?;
+
+    let mut passport = passport;
+    if passport.status == PendingCeremony {
+        passport = transition(
+            &txn,
+            passport,
+            Issued,
+            Some("issued on activation".to_string()),
+            actor,
+        )
+        .await?;
+    }
+    let passport = transition(&txn, passport, Active, reason, actor).await?;
+
+    let replaced: Vec<passport::Model> = Passport::find()
+        .filter(passport::Column::OwnerId.eq(passport.owner_id))
+        .filter(passport::Column::Id.ne(passport.id))
+        .filter(passport::Column::Status.is_in([Active, Suspended, Issued]))
+        .all(&txn)
+        .await?;
+
+    for old in replaced {
+        transition(
+            &txn,
+            old,
+            Revoked,
+            Some(format!("replaced by passport {}", passport.id)),
+            actor,
+        )
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(passport)
+}
@@ -0,0 +1,125 @@
+use entity::{prelude::*, sea_orm_active_enums::RoleEnum, user};
+use oxide_auth::endpoint::Scope;
+use sea_orm::prelude::*;
+use vercel_runtime::{Body, Request, Response, StatusCode};
+
+use crate::{audit, db, tfa, APIError};
+
+/// Something a role lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Be granted the `admin` scope
+    Admin,
+    /// Be granted the `admin:read` scope
+    AdminRead,
+    /// Be granted the `staff` scope, which staff-only endpoints take alongside `admin`
+    Staff,
+    /// Schedule, edit and cancel ceremonies
+    ManageCeremonies,
+    /// Check passports in at ceremonies and see who turned up
+    CheckIn,
+    /// Open the door with a passport
+    Door,
+}
+
+use Permission::*;
+
+/// Everything a role is allowed to do
+pub fn permissions(role: &RoleEnum) -> &'static [Permission] {
+    match role {
+        RoleEnum::Admin => &[Admin, AdminRead, Staff, ManageCeremonies, CheckIn, Door],
+        RoleEnum::Organizer => &[Staff, ManageCeremonies, CheckIn, Door],
+        RoleEnum::CeremonyStaff => &[Staff, CheckIn],
+        RoleEnum::DoorAccess | RoleEnum::Hacker => &[Door],
+        RoleEnum::Alumni => &[],
+    }
+}
+
+pub fn has(role: &RoleEnum, permission: Permission) -> bool {
+    permissions(role).contains(&permission)
+}
+
+/// Whether going from role `from` to role `to` takes away anything `from` was allowed to do
+pub fn is_downgrade(from: &RoleEnum, to: &RoleEnum) -> bool {
+    permissions(from).iter().any(|p| !has(to, *p))
+}
+
+/// The permission needed to be granted a scope, if it needs one
+fn scope_permission(scope: &str) -> Option<Permission> {
+    match scope {
+        "admin:read" => Some(AdminRead),
+        s if s.starts_with("admin") => Some(Admin),
+        "staff" => Some(Staff),
+        _ => None,
+    }
+}
+
+/// Whether someone with this role may be granted every scope in `scope`
+pub fn may_grant(role: &RoleEnum, scope: &Scope) -> bool {
+    scope
+        .iter()
+        .filter_map(scope_permission)
+        .all(|p| has(role, p))
+}
+
+/// Whether the role can reach admin or staff scopes, and so has to log in with TOTP or a
+/// passkey
+pub fn requires_totp(role: &RoleEnum) -> bool {
+    has(role, Admin) || has(role, AdminRead) || has(role, Staff)
+}
+
+/// Whether any scope in `scope` can only be granted after a second factor
+pub fn needs_totp(scope: &Scope) -> bool {
+    scope.iter().any(|s| scope_permission(s).is_some())
+}
+
+/// Checks the request's token, which needs the `admin` or `staff` scope, and that its
+/// owner's role has `permission`, returning the owner's id or the response to send back.
+/// Refusals are recorded in the audit log.
+pub async fn require(
+    req: Request,
+    permission: Permission,
+) -> Result<Result<i32, Response<Body>>, vercel_runtime::Error> {
+    let audit = audit::Event::new(&req, "permission")
+        .subject("path", req.uri().path())
+        .detail(serde_json::json!({ "permission": format!("{permission:?}") }));
+    let user_id = audit
+        .oauth_user(
+            req,
+            vec![
+                "admin".parse().expect("scope to parse"),
+                "staff".parse().expect("scope to parse"),
+            ],
+        )
+        .await?;
+
+    let db = db().await?;
+    let user: user::Model = User::find_by_id(user_id)
+        .one(&db)
+        .await?
+        .ok_or("User not found".to_string())?;
+
+    let audit = audit.actor(user_id);
+
+    if user.disabled_at.is_some() || !has(&user.role, permission) {
+        audit.denied(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "Your role doesn't allow this",
+            code: "forbidden_role",
+        })?));
+        *resp.status_mut() = StatusCode::FORBIDDEN;
+        return Ok(Err(resp));
+    }
+
+    if requires_totp(&user.role) && !tfa::enrolled(&db, &user).await? {
+        audit.denied(&db).await;
+        let mut resp = Response::new(Body::Text(serde_json::to_string(&APIError {
+            message: "Set up TOTP or a passkey before doing this",
+            code: "totp_required",
+        })?));
+        *resp.status_mut() = StatusCode::FORBIDDEN;
+        return Ok(Err(resp));
+    }
+
+    Ok(Ok(user_id))
+}
@@ -0,0 +1,61 @@
+use std::env;
+
+use chrono::{Duration, Utc};
+use oxide_auth::endpoint::Scope;
+use sea_orm::prelude::*;
+
+use crate::roles;
+
+/// Authentication method references (RFC 8176) for how someone logged in
+pub const PASSPORT: &str = "sc";
+pub const TOTP: &str = "otp";
+pub const PASSKEY: &str = "hwk";
+pub const MULTI_FACTOR: &str = "mfa";
+
+/// When and how the user behind an authorization last proved who they are
+#[derive(Debug, Clone)]
+pub struct Authentication {
+    /// The passport that was tapped
+    pub passport_id: Option<i32>,
+    pub auth_time: DateTimeWithTimeZone,
+    pub amr: Vec<String>,
+}
+
+impl Authentication {
+    /// A passport tap just now, along with whichever second factor came with it
+    pub fn tap(passport_id: i32, second_factor: Option<&str>) -> Self {
+        let mut amr = vec![PASSPORT.to_string()];
+        if let Some(method) = second_factor {
+            amr.push(method.to_string());
+            amr.push(MULTI_FACTOR.to_string());
+        }
+
+        Self {
+            passport_id: Some(passport_id),
+            auth_time: Utc::now().fixed_offset(),
+            amr,
+        }
+    }
+}
+
+/// How long ago a passport tap can be and still count for clients and scopes that need a fresh
+/// one. Set with `STEP_UP_MAX_AGE` in seconds, 15 minutes by default.
+pub fn max_age() -> Duration {
+    env::var("STEP_UP_MAX_AGE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::seconds)
+        .unwrap_or_else(|| Duration::minutes(15))
+}
+
+/// Whether an authorization has to come from a recent login rather than any session. That is
+/// anything with admin scopes, and every client listed in `STEP_UP_CLIENTS`.
+pub fn required(client_id: &str, scope: &Scope) -> bool {
+    roles::needs_totp(scope)
+        || env::var("STEP_UP_CLIENTS")
+            .is_ok_and(|clients| clients.split(',').any(|c| c.trim() == client_id))
+}
+
+pub fn is_fresh(auth_time: &DateTimeWithTimeZone) -> bool {
+    Utc::now().fixed_offset() - *auth_time <= max_age()
+}
@@ -1,4 +1,19 @@
+use chrono::Utc;
+use entity::{prelude::*, totp_recovery_code, user};
+use fred::prelude::*;
+use rand::distributions::{Alphanumeric, DistString};
+use sea_orm::{prelude::*, ActiveValue, Condition};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use totp_rs::{Secret, TOTP};
+use webauthn_rs::prelude::PublicKeyCredential;
+
+use crate::{constant_time_eq, envelope, kv, webauthn};
+
+/// How long a started enrolment waits for its first code
+const ENROLMENT_SECONDS: i64 = 600;
+/// How many recovery codes a user gets at once
+const RECOVERY_CODES: usize = 10;
 
 fn default_totp(user_id: i32, secret: Vec<u8>) -> TOTP {
     TOTP {
@@ -12,16 +27,215 @@ fn default_totp(user_id: i32, secret: Vec<u8>) -> TOTP {
     }
 }
 
-pub fn validate_totp(
-    user_id: i32,
-    secret: String,
-    code: &str,
-) -> Result<bool, vercel_runtime::Error> {
+/// The time step `code` was generated for, if it is one `skew` allows right now
+fn matching_step(user_id: i32, secret: String, code: &str) -> Option<i64> {
     let totp = default_totp(
         user_id,
         Secret::Encoded(secret)
             .to_bytes()
             .expect("secret to parse sucessfully"),
     );
-    Ok(totp.check_current(code)?)
+    let now = Utc::now().timestamp() as u64;
+    let steps = totp.skew as u64;
+
+    (0..=2 * steps)
+        .map(|i| (now / totp.step + i).saturating_sub(steps))
+        .find(|step| constant_time_eq(totp.generate(step * totp.step).as_bytes(), code.as_bytes()))
+        .map(|step| step as i64)
+}
+
+/// Whether the user has any second factor set up, TOTP or a passkey
+pub async fn enrolled<C: ConnectionTrait>(db: &C, user: &user::Model) -> Result<bool, DbErr> {
+    Ok(user.totp.is_some() || webauthn::has_credentials(db, user.id).await?)
+}
+
+/// A second factor the user already has, offered to back a change to their factors
+#[derive(Debug, Default, Deserialize)]
+pub struct Proof {
+    /// A TOTP or recovery code
+    pub totp_code: Option<String>,
+    /// A passkey assertion answering a challenge from `/api/user/webauthn`
+    pub webauthn: Option<PublicKeyCredential>,
+}
+
+/// Whether `proof` shows the user holds one of their second factors. Someone who hasn't set
+/// any up has nothing to prove.
+pub async fn proven<C: ConnectionTrait>(
+    db: &C,
+    user: &user::Model,
+    proof: &Proof,
+) -> Result<bool, vercel_runtime::Error> {
+    if !enrolled(db, user).await? {
+        return Ok(true);
+    }
+
+    if let Some(assertion) = &proof.webauthn {
+        webauthn::finish_authentication(db, user.id, assertion).await
+    } else if let Some(code) = &proof.totp_code {
+        verify(db, user, code).await
+    } else {
+        Ok(false)
+    }
+}
+
+/// Hash a recovery code is stored under, ignoring case and separators
+fn hash_recovery_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalised.as_bytes()))
+}
+
+/// Accepts a TOTP code or an unused recovery code for the user. TOTP codes are only good for
+/// a time step after the last one accepted, and recovery codes only once.
+pub async fn verify<C: ConnectionTrait>(
+    db: &C,
+    user: &user::Model,
+    code: &str,
+) -> Result<bool, vercel_runtime::Error> {
+    let Some(sealed) = user.totp.clone() else {
+        return Ok(false);
+    };
+    let secret = envelope::open(&sealed, &totp_context(user.id))?;
+
+    if let Some(step) = matching_step(user.id, secret.clone(), code) {
+        // Conditional so two requests racing with the same code can't both win
+        let mut update =
+            User::update_many().col_expr(user::Column::TotpLastStep, Expr::value(step));
+        // Move secrets sealed with a retired key over to the newest one
+        if !envelope::is_current(&sealed)? {
+            update = update.col_expr(
+                user::Column::Totp,
+                Expr::value(envelope::seal(&secret, &totp_context(user.id))?),
+            );
+        }
+        let res = update
+            .filter(user::Column::Id.eq(user.id))
+            .filter(
+                Condition::any()
+                    .add(user::Column::TotpLastStep.is_null())
+                    .add(user::Column::TotpLastStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+        return Ok(res.rows_affected == 1);
+    }
+
+    let res = TotpRecoveryCode::update_many()
+        .col_expr(
+            totp_recovery_code::Column::UsedAt,
+            Expr::value(Utc::now().fixed_offset()),
+        )
+        .filter(totp_recovery_code::Column::UserId.eq(user.id))
+        .filter(totp_recovery_code::Column::CodeHash.eq(hash_recovery_code(code)))
+        .filter(totp_recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected > 0)
+}
+
+/// Replaces the user's recovery codes with new ones, which are only ever shown here
+pub async fn generate_recovery_codes<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Vec<String>, vercel_runtime::Error> {
+    clear_recovery_codes(db, user_id).await?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = Alphanumeric
+                .sample_string(&mut rand::thread_rng(), 10)
+                .to_ascii_lowercase();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+
+    TotpRecoveryCode::insert_many(codes.iter().map(|code| totp_recovery_code::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        code_hash: ActiveValue::Set(hash_recovery_code(code)),
+        created_at: ActiveValue::NotSet,
+        used_at: ActiveValue::NotSet,
+    }))
+    .exec(db)
+    .await?;
+
+    Ok(codes)
+}
+
+pub async fn clear_recovery_codes<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<(), DbErr> {
+    TotpRecoveryCode::delete_many()
+        .filter(totp_recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// What an authenticator app needs to add a new secret
+#[derive(Debug, Serialize)]
+pub struct Enrolment {
+    pub secret: String,
+    pub otpauth_url: String,
+    /// PNG of the otpauth URL, base64 encoded
+    pub qr_code: String,
+}
+
+/// What a user's TOTP secret is sealed for, so it only opens for that user
+fn totp_context(user_id: i32) -> String {
+    format!("user.totp:{user_id}")
+}
+
+fn enrolment_key(user_id: i32) -> String {
+    format!("totp-enrol:{user_id}")
+}
+
+/// Generates a new secret for the user and keeps it aside until it is confirmed
+pub async fn start_enrolment(user_id: i32) -> Result<Enrolment, vercel_runtime::Error> {
+    let secret = Secret::generate_secret();
+    let totp = default_totp(
+        user_id,
+        secret.to_bytes().expect("generated secret to be valid"),
+    );
+    let encoded = secret.to_encoded().to_string();
+
+    let kv = kv().await?;
+    kv.set::<(), _, _>(
+        enrolment_key(user_id),
+        envelope::seal(&encoded, &enrolment_key(user_id))?,
+        Some(Expiration::EX(ENROLMENT_SECONDS)),
+        None,
+        false,
+    )
+    .await?;
+
+    Ok(Enrolment {
+        otpauth_url: totp.get_url(),
+        qr_code: totp.get_qr_base64()?,
+        secret: encoded,
+    })
+}
+
+/// The pending secret, sealed for storage, and the time step of `code`, if `code` was
+/// generated from it. The enrolment is used up on success.
+pub async fn confirm_enrolment(
+    user_id: i32,
+    code: &str,
+) -> Result<Option<(String, i64)>, vercel_runtime::Error> {
+    let kv = kv().await?;
+    let Some(sealed): Option<String> = kv.get(enrolment_key(user_id)).await? else {
+        return Ok(None);
+    };
+    let secret = envelope::open(&sealed, &enrolment_key(user_id))?;
+
+    let Some(step) = matching_step(user_id, secret.clone(), code) else {
+        return Ok(None);
+    };
+
+    kv.del::<(), _>(enrolment_key(user_id)).await?;
+    Ok(Some((
+        envelope::seal(&secret, &totp_context(user_id))?,
+        step,
+    )))
 }
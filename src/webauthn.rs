@@ -0,0 +1,325 @@
+use base64::prelude::*;
+use chrono::Utc;
+use entity::{prelude::*, user, webauthn_credential};
+use fred::prelude::*;
+use sea_orm::{prelude::*, ActiveValue, IntoActiveModel};
+use webauthn_rs::prelude::*;
+
+use crate::kv;
+
+const RP_ID: &str = "id.purduehackers.com";
+const RP_ORIGIN: &str = "https://id.purduehackers.com";
+/// How long a registration or login challenge stays valid
+const CHALLENGE_SECONDS: i64 = 300;
+
+fn webauthn() -> Result<Webauthn, WebauthnError> {
+    WebauthnBuilder::new(
+        RP_ID,
+        &Url::parse(RP_ORIGIN).expect("const URL to be valid"),
+    )?
+    .rp_name("Purdue Hackers")
+    .build()
+}
+
+fn registration_key(user_id: i32) -> String {
+    format!("webauthn-reg:{user_id}")
+}
+
+fn authentication_key(user_id: i32) -> String {
+    format!("webauthn-auth:{user_id}")
+}
+
+/// Every passkey the user has registered
+pub async fn credentials<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Vec<webauthn_credential::Model>, DbErr> {
+    WebauthnCredential::find()
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .all(db)
+        .await
+}
+
+pub async fn has_credentials<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<bool, DbErr> {
+    Ok(WebauthnCredential::find()
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .count(db)
+        .await?
+        > 0)
+}
+
+fn passkeys(credentials: &[webauthn_credential::Model]) -> Vec<Passkey> {
+    credentials
+        .iter()
+        .filter_map(|c| serde_json::from_value(c.passkey.clone()).ok())
+        .collect()
+}
+
+/// A challenge to create a passkey, along with the state to keep until it's answered
+fn registration_challenge(
+    user_id: i32,
+    name: &str,
+    existing: Vec<CredentialID>,
+) -> Result<(CreationChallengeResponse, String), vercel_runtime::Error> {
+    let (challenge, state) = webauthn()?.start_passkey_registration(
+        Uuid::from_u128(user_id as u128),
+        name,
+        name,
+        Some(existing),
+    )?;
+
+    Ok((challenge, serde_json::to_string(&state)?))
+}
+
+/// The passkey made by a registration, if it answers the challenge `state` was kept for
+fn registered(
+    state: &str,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<Option<Passkey>, vercel_runtime::Error> {
+    let state: PasskeyRegistration = serde_json::from_str(state)?;
+
+    Ok(webauthn()?
+        .finish_passkey_registration(credential, &state)
+        .ok())
+}
+
+/// A challenge to sign in with one of `passkeys`, along with the state to keep until it's
+/// answered
+fn authentication_challenge(
+    passkeys: &[Passkey],
+) -> Result<(RequestChallengeResponse, String), vercel_runtime::Error> {
+    let (challenge, state) = webauthn()?.start_passkey_authentication(passkeys)?;
+
+    Ok((challenge, serde_json::to_string(&state)?))
+}
+
+/// The result of an assertion, if it answers the challenge `state` was kept for
+fn authenticated(
+    state: &str,
+    assertion: &PublicKeyCredential,
+) -> Result<Option<AuthenticationResult>, vercel_runtime::Error> {
+    let state: PasskeyAuthentication = serde_json::from_str(state)?;
+
+    Ok(webauthn()?
+        .finish_passkey_authentication(assertion, &state)
+        .ok())
+}
+
+/// Challenges the user's browser to create a new passkey
+pub async fn start_registration<C: ConnectionTrait>(
+    db: &C,
+    user: &user::Model,
+) -> Result<CreationChallengeResponse, vercel_runtime::Error> {
+    let existing = passkeys(&credentials(db, user.id).await?)
+        .iter()
+        .map(|p| p.cred_id().clone())
+        .collect();
+    let name = user
+        .discord_username
+        .clone()
+        .unwrap_or_else(|| user.id.to_string());
+
+    let (challenge, state) = registration_challenge(user.id, &name, existing)?;
+
+    let kv = kv().await?;
+    kv.set::<(), _, _>(
+        registration_key(user.id),
+        state,
+        Some(Expiration::EX(CHALLENGE_SECONDS)),
+        None,
+        false,
+    )
+    .await?;
+
+    Ok(challenge)
+}
+
+/// Stores the passkey the browser made, if it answers the pending challenge
+pub async fn finish_registration<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    name: Option<String>,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<Option<webauthn_credential::Model>, vercel_runtime::Error> {
+    let kv = kv().await?;
+    let Some(state): Option<String> = kv.getdel(registration_key(user_id)).await? else {
+        return Ok(None);
+    };
+    let Some(passkey) = registered(&state, credential)? else {
+        return Ok(None);
+    };
+
+    let model = webauthn_credential::ActiveModel {
+        id: ActiveValue::NotSet,
+        user_id: ActiveValue::Set(user_id),
+        credential_id: ActiveValue::Set(BASE64_URL_SAFE_NO_PAD.encode(passkey.cred_id())),
+        passkey: ActiveValue::Set(serde_json::to_value(&passkey)?),
+        name: ActiveValue::Set(name),
+        created_at: ActiveValue::NotSet,
+        last_used_at: ActiveValue::NotSet,
+    }
+    .insert(db)
+    .await?;
+
+    Ok(Some(model))
+}
+
+/// Challenges the user to sign in with one of their passkeys, if they have any
+pub async fn start_authentication<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+) -> Result<Option<RequestChallengeResponse>, vercel_runtime::Error> {
+    let passkeys = passkeys(&credentials(db, user_id).await?);
+    if passkeys.is_empty() {
+        return Ok(None);
+    }
+
+    let (challenge, state) = authentication_challenge(&passkeys)?;
+
+    let kv = kv().await?;
+    kv.set::<(), _, _>(
+        authentication_key(user_id),
+        state,
+        Some(Expiration::EX(CHALLENGE_SECONDS)),
+        None,
+        false,
+    )
+    .await?;
+
+    Ok(Some(challenge))
+}
+
+/// Whether the assertion answers the user's pending challenge. The challenge is used up either
+/// way, and the passkey's signature counter is brought up to date on success.
+pub async fn finish_authentication<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    assertion: &PublicKeyCredential,
+) -> Result<bool, vercel_runtime::Error> {
+    let kv = kv().await?;
+    let Some(state): Option<String> = kv.getdel(authentication_key(user_id)).await? else {
+        return Ok(false);
+    };
+    let Some(result) = authenticated(&state, assertion)? else {
+        return Ok(false);
+    };
+
+    let Some(credential) = WebauthnCredential::find()
+        .filter(webauthn_credential::Column::UserId.eq(user_id))
+        .filter(
+            webauthn_credential::Column::CredentialId
+                .eq(BASE64_URL_SAFE_NO_PAD.encode(result.cred_id())),
+        )
+        .one(db)
+        .await?
+    else {
+        return Ok(false);
+    };
+
+    let mut passkey: Passkey = serde_json::from_value(credential.passkey.clone())?;
+    passkey.update_credential(&result);
+
+    let mut am = credential.into_active_model();
+    am.passkey = ActiveValue::Set(serde_json::to_value(&passkey)?);
+    am.last_used_at = ActiveValue::Set(Some(Utc::now().fixed_offset()));
+    am.update(db).await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use webauthn_authenticator_rs::{softpasskey::SoftPasskey, WebauthnAuthenticator};
+
+    use super::*;
+
+    fn origin() -> Url {
+        Url::parse(RP_ORIGIN).expect("const URL to be valid")
+    }
+
+    /// Registers a software passkey, returning it and what the server stored for it
+    fn register() -> (WebauthnAuthenticator<SoftPasskey>, Passkey) {
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        let (challenge, state) =
+            registration_challenge(1, "hacker", vec![]).expect("challenge to start");
+        let credential = authenticator
+            .do_registration(origin(), challenge)
+            .expect("passkey to register");
+        let passkey = registered(&state, &credential)
+            .expect("state to load")
+            .expect("registration to be accepted");
+
+        (authenticator, passkey)
+    }
+
+    #[test]
+    fn registers_and_authenticates() {
+        let (mut authenticator, mut passkey) = register();
+
+        let (challenge, state) =
+            authentication_challenge(slice::from_ref(&passkey)).expect("challenge to start");
+        let assertion = authenticator
+            .do_authentication(origin(), challenge)
+            .expect("passkey to sign");
+        let result = authenticated(&state, &assertion)
+            .expect("state to load")
+            .expect("assertion to be accepted");
+
+        assert_eq!(result.cred_id(), passkey.cred_id());
+        assert_eq!(passkey.update_credential(&result), Some(true));
+    }
+
+    #[test]
+    fn registration_for_another_challenge_is_refused() {
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        let (challenge, _) =
+            registration_challenge(1, "hacker", vec![]).expect("challenge to start");
+        let (_, other_state) =
+            registration_challenge(1, "hacker", vec![]).expect("challenge to start");
+        let credential = authenticator
+            .do_registration(origin(), challenge)
+            .expect("passkey to register");
+
+        assert!(registered(&other_state, &credential)
+            .expect("state to load")
+            .is_none());
+    }
+
+    #[test]
+    fn replayed_assertion_is_refused() {
+        let (mut authenticator, passkey) = register();
+
+        let (challenge, state) =
+            authentication_challenge(slice::from_ref(&passkey)).expect("challenge to start");
+        let assertion = authenticator
+            .do_authentication(origin(), challenge)
+            .expect("passkey to sign");
+        assert!(authenticated(&state, &assertion)
+            .expect("state to load")
+            .is_some());
+
+        // The challenge it answered is used up, so it can only be tried against a new one
+        let (_, state) = authentication_challenge(&[passkey]).expect("challenge to start");
+        assert!(authenticated(&state, &assertion)
+            .expect("state to load")
+            .is_none());
+    }
+
+    #[test]
+    fn assertion_for_another_challenge_is_refused() {
+        let (mut authenticator, passkey) = register();
+
+        let (challenge, _) =
+            authentication_challenge(slice::from_ref(&passkey)).expect("challenge to start");
+        let (_, other_state) = authentication_challenge(&[passkey]).expect("challenge to start");
+        let assertion = authenticator
+            .do_authentication(origin(), challenge)
+            .expect("passkey to sign");
+
+        assert!(authenticated(&other_state, &assertion)
+            .expect("state to load")
+            .is_none());
+    }
+}